@@ -0,0 +1,6 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+  tonic_build::configure()
+    .build_client(false)
+    .compile(&["proto/product.proto"], &["proto"])?;
+  Ok(())
+}
@@ -0,0 +1,316 @@
+// Copyright (C) 2020 Peter Mezei
+//
+// This file is part of Gardenzilla.
+//
+// Gardenzilla is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// Gardenzilla is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Gardenzilla.  If not, see <http://www.gnu.org/licenses/>.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum AttributeKind {
+  Text,
+  Integer,
+  Boolean,
+  // Allowed options
+  Enum(Vec<String>),
+}
+
+impl std::fmt::Display for AttributeKind {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match &self {
+      AttributeKind::Text => write!(f, "text"),
+      AttributeKind::Integer => write!(f, "integer"),
+      AttributeKind::Boolean => write!(f, "boolean"),
+      AttributeKind::Enum(_) => write!(f, "enum"),
+    }
+  }
+}
+
+impl AttributeKind {
+  /// Try to create kind from its name and options
+  /// Options are only allowed for enum
+  pub fn try_new(kind: &str, options: Vec<String>) -> Result<AttributeKind, String> {
+    let res = match kind.trim().to_lowercase().as_str() {
+      "text" => AttributeKind::Text,
+      "integer" => AttributeKind::Integer,
+      "boolean" => AttributeKind::Boolean,
+      "enum" => {
+        let options = options
+          .into_iter()
+          .map(|o| o.trim().to_string())
+          .collect::<Vec<String>>();
+        if options.is_empty() || options.iter().any(|o| o.is_empty()) {
+          return Err("A választható értékek nem lehetnek üresek!".to_string());
+        }
+        for (i, option) in options.iter().enumerate() {
+          if options[..i]
+            .iter()
+            .any(|o| o.to_lowercase() == option.to_lowercase())
+          {
+            return Err(format!("Ismétlődő választható érték: {}", option));
+          }
+        }
+        return Ok(AttributeKind::Enum(options));
+      }
+      _ => return Err(format!("Ismeretlen tulajdonság típus: {}", kind)),
+    };
+    if !options.is_empty() {
+      return Err("Választható értékek csak enum típusnál adhatók meg!".to_string());
+    }
+    Ok(res)
+  }
+  /// Enum options, empty for other kinds
+  pub fn options(&self) -> Vec<String> {
+    match self {
+      AttributeKind::Enum(options) => options.clone(),
+      _ => Vec::new(),
+    }
+  }
+}
+
+/// Attribute definition in a product attribute schema
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AttributeDef {
+  /// Attribute name, e.g. color
+  pub name: String,
+  pub kind: AttributeKind,
+  /// Every SKU must have a value
+  pub required: bool,
+  /// Value is part of the SKU display name
+  pub show_in_name: bool,
+}
+
+impl std::fmt::Display for AttributeDef {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}: {}", self.name, self.kind)?;
+    if let AttributeKind::Enum(options) = &self.kind {
+      write!(f, "({})", options.join("|"))?;
+    }
+    if self.required {
+      write!(f, "*")?;
+    }
+    Ok(())
+  }
+}
+
+impl AttributeDef {
+  pub fn try_new(
+    name: &str,
+    kind: AttributeKind,
+    required: bool,
+    show_in_name: bool,
+  ) -> Result<AttributeDef, String> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+      return Err("A tulajdonság neve nem lehet üres!".to_string());
+    }
+    Ok(AttributeDef {
+      name,
+      kind,
+      required,
+      show_in_name,
+    })
+  }
+  /// Validate raw value against the attribute kind
+  /// and return its normalized form
+  pub fn normalize(&self, value: &str) -> Result<String, String> {
+    let value = value.trim();
+    match &self.kind {
+      AttributeKind::Text => Ok(value.to_string()),
+      AttributeKind::Integer => value
+        .parse::<i64>()
+        .map(|v| v.to_string())
+        .map_err(|_| format!("A(z) {} értéke csak egész szám lehet!", self.name)),
+      AttributeKind::Boolean => match value.to_lowercase().as_str() {
+        "igen" | "true" | "1" => Ok("igen".to_string()),
+        "nem" | "false" | "0" => Ok("nem".to_string()),
+        _ => Err(format!(
+          "A(z) {} értéke csak igen vagy nem lehet!",
+          self.name
+        )),
+      },
+      AttributeKind::Enum(options) => options
+        .iter()
+        .find(|o| o.to_lowercase() == value.to_lowercase())
+        .cloned()
+        .ok_or_else(|| {
+          format!(
+            "A(z) {} értéke csak a következők egyike lehet: {}",
+            self.name,
+            options.join(", ")
+          )
+        }),
+    }
+  }
+}
+
+/// Validated attribute value of a SKU
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AttributeValue {
+  pub name: String,
+  pub value: String,
+  /// Copied from the related AttributeDef
+  pub show_in_name: bool,
+}
+
+impl std::fmt::Display for AttributeValue {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}={}", self.name, self.value)
+  }
+}
+
+/// Check schema attribute names are unique
+/// Names are case insensitive
+pub fn validate_schema(schema: &[AttributeDef]) -> Result<(), String> {
+  for (i, def) in schema.iter().enumerate() {
+    if schema[..i]
+      .iter()
+      .any(|d| d.name.to_lowercase() == def.name.to_lowercase())
+    {
+      return Err(format!("Ismétlődő tulajdonság: {}", def.name));
+    }
+  }
+  Ok(())
+}
+
+/// Validate name, value pairs against a schema
+/// Empty values are treated as not set.
+/// Result is normalized and ordered by the schema.
+pub fn validate_values(
+  schema: &[AttributeDef],
+  values: Vec<(String, String)>,
+) -> Result<Vec<AttributeValue>, String> {
+  let mut res: Vec<Option<AttributeValue>> = vec![None; schema.len()];
+  for (name, value) in values {
+    let index = schema
+      .iter()
+      .position(|d| d.name.to_lowercase() == name.trim().to_lowercase())
+      .ok_or_else(|| format!("A termékhez nem tartozik ilyen tulajdonság: {}", name))?;
+    if res[index].is_some() {
+      return Err(format!("Ismétlődő tulajdonság: {}", name));
+    }
+    if value.trim().is_empty() {
+      continue;
+    }
+    let def = &schema[index];
+    res[index] = Some(AttributeValue {
+      name: def.name.clone(),
+      value: def.normalize(&value)?,
+      show_in_name: def.show_in_name,
+    });
+  }
+  // Check required attributes
+  if let Some(def) = schema
+    .iter()
+    .zip(res.iter())
+    .find(|(d, v)| d.required && v.is_none())
+    .map(|(d, _)| d)
+  {
+    return Err(format!("Kötelező tulajdonság hiányzik: {}", def.name));
+  }
+  Ok(res.into_iter().flatten().collect())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn schema() -> Vec<AttributeDef> {
+    vec![
+      AttributeDef::try_new(
+        "szín",
+        AttributeKind::try_new("enum", vec!["piros".into(), "kék".into()]).unwrap(),
+        true,
+        true,
+      )
+      .unwrap(),
+      AttributeDef::try_new("szemcse", AttributeKind::Integer, false, false).unwrap(),
+    ]
+  }
+
+  #[test]
+  fn test_kind_try_new() {
+    assert_eq!(
+      AttributeKind::try_new("Integer", vec![]).unwrap(),
+      AttributeKind::Integer
+    );
+    assert!(AttributeKind::try_new("text", vec!["a".into()]).is_err());
+    assert!(AttributeKind::try_new("enum", vec![]).is_err());
+    assert!(AttributeKind::try_new("enum", vec!["S".into(), "s".into()]).is_err());
+    assert!(AttributeKind::try_new("color", vec![]).is_err());
+  }
+
+  #[test]
+  fn test_validate_schema() {
+    assert!(validate_schema(&schema()).is_ok());
+    let mut schema = schema();
+    schema.push(AttributeDef::try_new("Szín", AttributeKind::Text, false, false).unwrap());
+    assert!(validate_schema(&schema).is_err());
+  }
+
+  #[test]
+  fn test_validate_values() {
+    let schema = schema();
+    // Normalized and ordered by schema
+    assert_eq!(
+      validate_values(
+        &schema,
+        vec![
+          ("szemcse".into(), " 080 ".into()),
+          ("Szín".into(), "PIROS".into())
+        ]
+      )
+      .unwrap(),
+      vec![
+        AttributeValue {
+          name: "szín".into(),
+          value: "piros".into(),
+          show_in_name: true,
+        },
+        AttributeValue {
+          name: "szemcse".into(),
+          value: "80".into(),
+          show_in_name: false,
+        },
+      ]
+    );
+    // Required missing
+    assert!(validate_values(&schema, vec![("szemcse".into(), "80".into())]).is_err());
+    // Wrong values
+    assert!(validate_values(&schema, vec![("szín".into(), "zöld".into())]).is_err());
+    assert!(validate_values(
+      &schema,
+      vec![
+        ("szín".into(), "kék".into()),
+        ("szemcse".into(), "x".into())
+      ]
+    )
+    .is_err());
+    // Unknown attribute
+    assert!(validate_values(
+      &schema,
+      vec![("szín".into(), "kék".into()), ("méret".into(), "XL".into())]
+    )
+    .is_err());
+    // Duplicated attribute
+    assert!(validate_values(
+      &schema,
+      vec![
+        ("szín".into(), "kék".into()),
+        ("szín".into(), "piros".into())
+      ]
+    )
+    .is_err());
+  }
+}
@@ -0,0 +1,243 @@
+// Copyright (C) 2020 Peter Mezei
+//
+// This file is part of Gardenzilla.
+//
+// Gardenzilla is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// Gardenzilla is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Gardenzilla.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum BarcodeKind {
+  Ean13,
+  Ean8,
+  UpcA,
+  // Case / carton code
+  Gtin14,
+  // Own, not GS1 code
+  Internal,
+}
+
+impl std::fmt::Display for BarcodeKind {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match &self {
+      BarcodeKind::Ean13 => write!(f, "ean13"),
+      BarcodeKind::Ean8 => write!(f, "ean8"),
+      BarcodeKind::UpcA => write!(f, "upca"),
+      BarcodeKind::Gtin14 => write!(f, "gtin14"),
+      BarcodeKind::Internal => write!(f, "internal"),
+    }
+  }
+}
+
+impl BarcodeKind {
+  pub fn try_from_str(from: &str) -> ServiceResult<BarcodeKind> {
+    let res = match from.trim().to_lowercase().as_str() {
+      "ean13" | "ean-13" => BarcodeKind::Ean13,
+      "ean8" | "ean-8" => BarcodeKind::Ean8,
+      "upca" | "upc-a" | "upc" => BarcodeKind::UpcA,
+      "gtin14" | "gtin-14" => BarcodeKind::Gtin14,
+      "internal" => BarcodeKind::Internal,
+      _ => {
+        return Err(ServiceError::bad_request(&format!(
+          "Ismeretlen vonalkód típus: {}",
+          from
+        )))
+      }
+    };
+    Ok(res)
+  }
+  /// Required length for GTIN kinds
+  fn gtin_length(&self) -> Option<usize> {
+    match self {
+      BarcodeKind::Ean13 => Some(13),
+      BarcodeKind::Ean8 => Some(8),
+      BarcodeKind::UpcA => Some(12),
+      BarcodeKind::Gtin14 => Some(14),
+      BarcodeKind::Internal => None,
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Barcode {
+  pub code: String,
+  pub kind: BarcodeKind,
+}
+
+impl Barcode {
+  /// Try to create a validated barcode
+  /// If kind is None, then we detect it by the code length.
+  /// Internal codes must be requested explicitly.
+  pub fn try_new(code: &str, kind: Option<BarcodeKind>) -> ServiceResult<Barcode> {
+    let code = code.trim().to_string();
+    let kind = match kind {
+      Some(kind) => kind,
+      None => match code.len() {
+        8 => BarcodeKind::Ean8,
+        12 => BarcodeKind::UpcA,
+        13 => BarcodeKind::Ean13,
+        14 => BarcodeKind::Gtin14,
+        _ => {
+          return Err(ServiceError::bad_request(
+            "A vonalkód típusa nem állapítható meg a hosszából!",
+          ))
+        }
+      },
+    };
+    match kind.gtin_length() {
+      Some(length) => {
+        if code.len() != length || !code.chars().all(|c| c.is_ascii_digit()) {
+          return Err(ServiceError::bad_request(&format!(
+            "A(z) {} vonalkód pontosan {} számjegyből állhat!",
+            kind, length
+          )));
+        }
+        if !is_valid_gtin(&code) {
+          return Err(ServiceError::bad_request(&format!(
+            "Hibás ellenőrző számjegy: {}",
+            code
+          )));
+        }
+      }
+      None => {
+        if code.is_empty()
+          || code.len() > 48
+          || !code.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        {
+          return Err(ServiceError::bad_request(
+            "A belső kód 1-48 karakter hosszú lehet, és csak betűt, számot és kötőjelet tartalmazhat!",
+          ));
+        }
+      }
+    }
+    Ok(Barcode { code, kind })
+  }
+  /// Global unique key of the barcode
+  /// GTIN codes are zero padded to 14 digits,
+  /// so the same GTIN as EAN-13 and GTIN-14 collides.
+  pub fn index_key(&self) -> String {
+    match self.kind {
+      BarcodeKind::Internal => internal_key(&self.code),
+      _ => gtin_key(&self.code),
+    }
+  }
+}
+
+fn gtin_key(code: &str) -> String {
+  format!("{:0>14}", code)
+}
+
+fn internal_key(code: &str) -> String {
+  format!("internal:{}", code.to_uppercase())
+}
+
+/// Possible index keys of a scanned code
+pub fn lookup_keys(code: &str) -> Vec<String> {
+  let code = code.trim();
+  let mut res = Vec::new();
+  if (code.len() == 8 || (12..=14).contains(&code.len()))
+    && code.chars().all(|c| c.is_ascii_digit())
+    && is_valid_gtin(code)
+  {
+    res.push(gtin_key(code));
+  }
+  res.push(internal_key(code));
+  res
+}
+
+/// Calculate GS1 check digit from the code without its check digit
+pub fn gtin_check_digit(digits: &str) -> u32 {
+  let sum: u32 = digits
+    .chars()
+    .rev()
+    .filter_map(|c| c.to_digit(10))
+    .enumerate()
+    .map(|(i, d)| if i % 2 == 0 { d * 3 } else { d })
+    .sum();
+  (10 - sum % 10) % 10
+}
+
+/// Validate GTIN check digit
+pub fn is_valid_gtin(code: &str) -> bool {
+  match code.chars().last().and_then(|c| c.to_digit(10)) {
+    Some(check_digit) => gtin_check_digit(&code[..code.len() - 1]) == check_digit,
+    None => false,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_gtin_check_digit() {
+    assert!(is_valid_gtin("5901234123457"));
+    assert!(is_valid_gtin("4006381333931"));
+    assert!(is_valid_gtin("96385074"));
+    assert!(is_valid_gtin("036000291452"));
+    assert!(is_valid_gtin("10012345678902"));
+    assert!(!is_valid_gtin("5901234123458"));
+    assert!(!is_valid_gtin("96385075"));
+    assert_eq!(gtin_check_digit("599820012345"), 0);
+  }
+
+  #[test]
+  fn test_barcode_try_new() {
+    assert_eq!(
+      Barcode::try_new("5901234123457", None).unwrap().kind,
+      BarcodeKind::Ean13
+    );
+    assert_eq!(
+      Barcode::try_new(" 96385074 ", None).unwrap().kind,
+      BarcodeKind::Ean8
+    );
+    assert_eq!(
+      Barcode::try_new("036000291452", None).unwrap().kind,
+      BarcodeKind::UpcA
+    );
+    assert_eq!(
+      Barcode::try_new("10012345678902", None).unwrap().kind,
+      BarcodeKind::Gtin14
+    );
+    assert!(Barcode::try_new("5901234123458", None).is_err());
+    assert!(Barcode::try_new("590123412345a", None).is_err());
+    assert!(Barcode::try_new("12345", None).is_err());
+    assert!(Barcode::try_new("96385074", Some(BarcodeKind::Ean13)).is_err());
+    assert!(Barcode::try_new("GZ-1234", Some(BarcodeKind::Internal)).is_ok());
+    assert!(Barcode::try_new("GZ 1234", Some(BarcodeKind::Internal)).is_err());
+    assert!(Barcode::try_new("", Some(BarcodeKind::Internal)).is_err());
+  }
+
+  #[test]
+  fn test_index_key() {
+    // Same GTIN as UPC-A, EAN-13 and GTIN-14
+    let upc = Barcode::try_new("036000291452", None).unwrap();
+    let ean = Barcode::try_new("0036000291452", None).unwrap();
+    let gtin = Barcode::try_new("00036000291452", None).unwrap();
+    assert_eq!(upc.index_key(), ean.index_key());
+    assert_eq!(ean.index_key(), gtin.index_key());
+    // Internal codes never collide with GTINs
+    let internal = Barcode::try_new("00036000291452", Some(BarcodeKind::Internal)).unwrap();
+    assert_ne!(internal.index_key(), gtin.index_key());
+    assert_eq!(
+      lookup_keys("036000291452"),
+      vec![
+        "00036000291452".to_string(),
+        "internal:036000291452".to_string()
+      ]
+    );
+    assert_eq!(lookup_keys("gz-1"), vec!["internal:GZ-1".to_string()]);
+  }
+}
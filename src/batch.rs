@@ -0,0 +1,494 @@
+// Copyright (C) 2020 Peter Mezei
+//
+// This file is part of Gardenzilla.
+//
+// Gardenzilla is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// Gardenzilla is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Gardenzilla.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::prelude::*;
+use crate::product::{Product, Sku};
+use crate::proto::{BatchCatalogRequest, NewProduct, NewSku, ProductObj, SkuObj};
+use crate::quantity::{Quantity, Unit};
+use crate::validate::{self, Violation};
+use std::collections::BTreeMap;
+
+/// Result of a validated batch
+/// Nothing is stored yet
+pub struct Plan {
+  /// Changed products with their stored version, None if new
+  pub products: Vec<(Option<Product>, Product)>,
+  /// Changed SKUs with their stored version, None if new
+  pub skus: Vec<(Option<Sku>, Sku)>,
+  /// Result IDs in request order
+  pub new_products: Vec<u32>,
+  pub product_updates: Vec<u32>,
+  pub new_skus: Vec<u32>,
+  pub sku_updates: Vec<u32>,
+}
+
+/// Working copy of the catalog
+/// Batch items are applied to it one by one
+struct Catalog<'a> {
+  stored_products: BTreeMap<u32, &'a Product>,
+  stored_skus: BTreeMap<u32, &'a Sku>,
+  products: BTreeMap<u32, Product>,
+  skus: BTreeMap<u32, Sku>,
+  // Batch item which changed the SKU, e.g. new_skus[2]
+  sku_items: BTreeMap<u32, String>,
+  next_product_id: u32,
+  next_sku_id: u32,
+}
+
+impl<'a> Catalog<'a> {
+  fn new<P, S>(products: P, skus: S, (next_product_id, next_sku_id): (u32, u32)) -> Self
+  where
+    P: Iterator<Item = &'a Product>,
+    S: Iterator<Item = &'a Sku>,
+  {
+    let stored_products = products
+      .map(|p| (p.product_id, p))
+      .collect::<BTreeMap<u32, &Product>>();
+    let stored_skus = skus.map(|s| (s.sku, s)).collect::<BTreeMap<u32, &Sku>>();
+    Self {
+      next_product_id,
+      next_sku_id,
+      stored_products,
+      stored_skus,
+      products: BTreeMap::new(),
+      skus: BTreeMap::new(),
+      sku_items: BTreeMap::new(),
+    }
+  }
+  /// Current version of a product
+  fn product(&self, product_id: u32) -> ServiceResult<Product> {
+    match self.products.get(&product_id) {
+      Some(p) => Ok(p.clone()),
+      None => self
+        .stored_products
+        .get(&product_id)
+        .map(|p| (*p).clone())
+        .ok_or_else(|| ServiceError::not_found(&format!("A termék nem található: {}", product_id))),
+    }
+  }
+  /// Current version of a SKU
+  fn sku(&self, sku: u32) -> ServiceResult<Sku> {
+    match self.skus.get(&sku) {
+      Some(s) => Ok(s.clone()),
+      None => self
+        .stored_skus
+        .get(&sku)
+        .map(|s| (*s).clone())
+        .ok_or_else(|| ServiceError::not_found(&format!("A SKU nem található: {}", sku))),
+    }
+  }
+  fn create_product(&mut self, r: NewProduct, tax_classes: &[u32]) -> ServiceResult<u32> {
+    if !tax_classes.contains(&r.tax_class_id) {
+      return Err(ServiceError::bad_request(
+        "A megadott adóosztály nem létezik!",
+      ));
+    }
+    let mut new = Product::new(
+      self.next_product_id,
+      r.name,
+      r.description,
+      Unit::try_from_str(&r.unit)?,
+      r.created_by,
+    );
+    new.set_tax_class(r.tax_class_id);
+    self.next_product_id += 1;
+    self.products.insert(new.product_id, new.clone());
+    Ok(new.product_id)
+  }
+  fn update_product(&mut self, r: ProductObj) -> ServiceResult<u32> {
+    let mut product = self.product(r.product_id)?;
+    check_version(product.version, r.version)?;
+    product.update(r.name, r.description, Unit::try_from_str(&r.unit)?);
+    // Related SKUs follow the product
+    for sku in product.skus.clone() {
+      let mut s = self.sku(sku)?;
+      s.update_parent(&product);
+      self.skus.insert(sku, s);
+    }
+    self.products.insert(product.product_id, product);
+    Ok(r.product_id)
+  }
+  fn create_sku(&mut self, r: NewSku, attributes: Vec<(String, String)>) -> ServiceResult<u32> {
+    let mut parent = self.product(r.product_id)?;
+    if parent.archived {
+      return Err(ServiceError::bad_request(
+        "A SKU nem hozható létre, a megadott termék archivált!",
+      ));
+    }
+    let mut new = Sku::new(
+      self.next_sku_id,
+      r.product_id,
+      &parent,
+      r.sub_name,
+      Quantity::try_from_str_in(&r.quantity, &parent.unit)?,
+      r.created_by,
+    );
+    new
+      .set_attributes(&parent.attributes, attributes)
+      .map_err(|e| ServiceError::bad_request(&e))?;
+    self.next_sku_id += 1;
+    parent.add_sku(new.sku);
+    self.products.insert(parent.product_id, parent);
+    self.skus.insert(new.sku, new.clone());
+    Ok(new.sku)
+  }
+  fn update_sku(&mut self, r: SkuObj) -> ServiceResult<u32> {
+    let mut sku = self.sku(r.sku)?;
+    check_version(sku.version, r.version)?;
+    let quantity = Quantity::try_from_str_in(&r.quantity, &sku.unit)?;
+    sku.update(r.subname, quantity);
+    self.skus.insert(sku.sku, sku);
+    Ok(r.sku)
+  }
+  /// Validate every changed object in its final state
+  fn violations(&self, product_items: &BTreeMap<u32, String>) -> Vec<Violation> {
+    let mut res = Vec::new();
+    for (id, product) in &self.products {
+      let item = product_items
+        .get(id)
+        .cloned()
+        .unwrap_or_else(|| format!("products[{}]", id));
+      res.extend(prefixed(&item, validate::product(product)));
+    }
+    for (id, sku) in &self.skus {
+      let item = self
+        .sku_items
+        .get(id)
+        .cloned()
+        .unwrap_or_else(|| format!("skus[{}]", id));
+      let parent = self.product(sku.product_id).ok();
+      res.extend(prefixed(&item, validate::sku(sku, parent.as_ref())));
+    }
+    res
+  }
+}
+
+/// Prefix violation fields with the batch item, e.g. new_skus[2].quantity
+fn prefixed(item: &str, violations: Vec<Violation>) -> Vec<Violation> {
+  violations
+    .into_iter()
+    .map(|v| Violation {
+      field: format!("{}.{}", item, v.field),
+      message: v.message,
+    })
+    .collect()
+}
+
+/// Turn an item error into violations
+fn item_violations(item: &str, error: ServiceError) -> Vec<Violation> {
+  match error {
+    ServiceError::Invalid(violations) => prefixed(item, violations),
+    error => vec![Violation {
+      field: item.to_string(),
+      message: error.to_string(),
+    }],
+  }
+}
+
+/// Validate a batch against the stored catalog
+/// Items are applied in order: new products, product updates,
+/// new SKUs, then SKU updates. Every problem of every item is
+/// collected into one report, otherwise the returned plan
+/// contains every change to store.
+/// New records get IDs from next_ids (product ID, SKU ID) on.
+pub fn plan<'a, P, S>(
+  products: P,
+  skus: S,
+  next_ids: (u32, u32),
+  tax_classes: &[u32],
+  r: BatchCatalogRequest,
+) -> ServiceResult<Plan>
+where
+  P: Iterator<Item = &'a Product>,
+  S: Iterator<Item = &'a Sku>,
+{
+  let mut catalog = Catalog::new(products, skus, next_ids);
+  let mut violations = Vec::new();
+  // Batch item which created or updated the product
+  let mut product_items = BTreeMap::new();
+  // None for the invalid items, so indexes remain usable
+  let mut new_products = Vec::new();
+  for (index, p) in r.new_products.into_iter().enumerate() {
+    let item = format!("new_products[{}]", index);
+    match catalog.create_product(p, tax_classes) {
+      Ok(id) => {
+        product_items.insert(id, item);
+        new_products.push(Some(id));
+      }
+      Err(e) => {
+        violations.extend(item_violations(&item, e));
+        new_products.push(None);
+      }
+    }
+  }
+  let mut product_updates = Vec::new();
+  for (index, p) in r.product_updates.into_iter().enumerate() {
+    let item = format!("product_updates[{}]", index);
+    match catalog.update_product(p) {
+      Ok(id) => {
+        product_items.entry(id).or_insert(item);
+        product_updates.push(id);
+      }
+      // Stale version aborts the whole batch, the client must reload anyway
+      Err(ServiceError::Conflict(msg)) => {
+        return Err(ServiceError::conflict(&format!("{}: {}", item, msg)))
+      }
+      Err(e) => violations.extend(item_violations(&item, e)),
+    }
+  }
+  let mut new_skus = Vec::new();
+  for (index, s) in r.new_skus.into_iter().enumerate() {
+    let item = format!("new_skus[{}]", index);
+    let mut sku = s.sku.unwrap_or_default();
+    // Refer to a product created by this batch
+    if s.new_product_index > 0 {
+      match new_products.get(s.new_product_index as usize - 1) {
+        Some(Some(id)) => sku.product_id = *id,
+        // Invalid product, already reported
+        Some(None) => continue,
+        None => {
+          violations.push(Violation {
+            field: format!("{}.new_product_index", item),
+            message: format!("Nincs ilyen sorszámú új termék: {}", s.new_product_index),
+          });
+          continue;
+        }
+      }
+    }
+    let attributes = sku
+      .attributes
+      .drain(..)
+      .map(|a| (a.name, a.value))
+      .collect();
+    match catalog.create_sku(sku, attributes) {
+      Ok(id) => {
+        catalog.sku_items.insert(id, item);
+        new_skus.push(id);
+      }
+      Err(e) => violations.extend(item_violations(&item, e)),
+    }
+  }
+  let mut sku_updates = Vec::new();
+  for (index, s) in r.sku_updates.into_iter().enumerate() {
+    let item = format!("sku_updates[{}]", index);
+    match catalog.update_sku(s) {
+      Ok(id) => {
+        catalog.sku_items.entry(id).or_insert(item);
+        sku_updates.push(id);
+      }
+      // Stale version aborts the whole batch, the client must reload anyway
+      Err(ServiceError::Conflict(msg)) => {
+        return Err(ServiceError::conflict(&format!("{}: {}", item, msg)))
+      }
+      Err(e) => violations.extend(item_violations(&item, e)),
+    }
+  }
+  violations.extend(catalog.violations(&product_items));
+  validate::check(violations)?;
+  Ok(Plan {
+    products: catalog
+      .products
+      .iter()
+      .map(|(id, p)| {
+        let old = catalog.stored_products.get(id).map(|s| (*s).clone());
+        let mut new = p.clone();
+        if old.is_some() {
+          new.bump_version();
+        }
+        (old, new)
+      })
+      .collect(),
+    skus: catalog
+      .skus
+      .iter()
+      .map(|(id, s)| {
+        let old = catalog.stored_skus.get(id).map(|o| (*o).clone());
+        let mut new = s.clone();
+        if old.is_some() {
+          new.bump_version();
+        }
+        (old, new)
+      })
+      .collect(),
+    new_products: new_products.into_iter().flatten().collect(),
+    product_updates,
+    new_skus,
+    sku_updates,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::proto::BatchNewSku;
+
+  fn new_product(name: &str, unit: &str) -> NewProduct {
+    NewProduct {
+      name: name.to_string(),
+      description: String::default(),
+      unit: unit.to_string(),
+      created_by: 1,
+      tax_class_id: 1,
+    }
+  }
+
+  fn new_sku(new_product_index: u32, quantity: &str) -> BatchNewSku {
+    BatchNewSku {
+      sku: Some(NewSku {
+        product_id: 0,
+        sub_name: String::default(),
+        quantity: quantity.to_string(),
+        created_by: 1,
+        attributes: Vec::new(),
+      }),
+      new_product_index,
+    }
+  }
+
+  fn fields(error: ServiceError) -> Vec<String> {
+    match error {
+      ServiceError::Invalid(violations) => violations.into_iter().map(|v| v.field).collect(),
+      _ => Vec::new(),
+    }
+  }
+
+  #[test]
+  fn test_plan() {
+    let stored = [Product::new(
+      1,
+      "Virágföld".into(),
+      "".into(),
+      Unit::Gram,
+      1,
+    )];
+    let request = BatchCatalogRequest {
+      new_products: vec![new_product("Kerti tömlő", "mm"), new_product("Ásó", "db")],
+      new_skus: vec![new_sku(1, "25 m"), new_sku(2, "1"), new_sku(2, "2")],
+      ..BatchCatalogRequest::default()
+    };
+    let res = plan(stored.iter(), std::iter::empty(), (2, 1), &[1], request).unwrap();
+    assert_eq!(res.new_products, vec![2, 3]);
+    assert_eq!(res.new_skus, vec![1, 2, 3]);
+    assert_eq!(res.products.len(), 2);
+    assert_eq!(res.skus.len(), 3);
+    // New SKUs are added to their new product
+    assert_eq!(res.products[1].1.skus, vec![2, 3]);
+    assert!(res.products.iter().all(|(old, _)| old.is_none()));
+    assert_eq!(res.skus[0].1.quantity, Quantity::Simple(25000));
+  }
+
+  #[test]
+  fn test_plan_report() {
+    let stored = [Product::new(
+      1,
+      "Virágföld".into(),
+      "".into(),
+      Unit::Gram,
+      1,
+    )];
+    let mut bad_tax = new_product("Ásó", "db");
+    bad_tax.tax_class_id = 2;
+    let mut unknown_product = new_sku(0, "1");
+    unknown_product.sku.as_mut().unwrap().product_id = 9;
+    let request = BatchCatalogRequest {
+      new_products: vec![bad_tax, new_product("", "db")],
+      new_skus: vec![
+        new_sku(2, "1,5"),
+        new_sku(5, "1"),
+        unknown_product,
+        new_sku(2, "0"),
+      ],
+      ..BatchCatalogRequest::default()
+    };
+    match plan(stored.iter(), std::iter::empty(), (2, 1), &[1], request) {
+      Ok(_) => panic!("Invalid batch must be rejected"),
+      Err(e) => assert_eq!(
+        fields(e),
+        vec![
+          "new_products[0]",
+          "new_skus[1].new_product_index",
+          "new_skus[2]",
+          "new_products[1].name",
+          "new_skus[0].quantity",
+          "new_skus[3].quantity",
+        ]
+      ),
+    }
+  }
+
+  #[test]
+  fn test_plan_version() {
+    let mut product = Product::new(1, "Virágföld".into(), "".into(), Unit::Gram, 1);
+    product.add_sku(1);
+    let sku = Sku::new(1, 1, &product, "".into(), Quantity::Simple(500), 1);
+    let request = |version: u32| BatchCatalogRequest {
+      product_updates: vec![ProductObj {
+        product_id: 1,
+        name: "Virágföld".into(),
+        unit: "g".into(),
+        version,
+        ..ProductObj::default()
+      }],
+      sku_updates: vec![SkuObj {
+        sku: 1,
+        quantity: "1 kg".into(),
+        version,
+        ..SkuObj::default()
+      }],
+      ..BatchCatalogRequest::default()
+    };
+    let res = plan(
+      std::iter::once(&product),
+      std::iter::once(&sku),
+      (2, 2),
+      &[1],
+      request(1),
+    )
+    .unwrap();
+    assert_eq!(res.products[0].1.version, 2);
+    assert_eq!(res.skus[0].1.version, 2);
+    // Stale version
+    match plan(
+      std::iter::once(&product),
+      std::iter::once(&sku),
+      (2, 2),
+      &[1],
+      request(2),
+    ) {
+      Ok(_) => panic!("Stale batch must be rejected"),
+      Err(e) => assert!(matches!(e, ServiceError::Conflict(_))),
+    }
+    // Missing version
+    match plan(
+      std::iter::once(&product),
+      std::iter::once(&sku),
+      (2, 2),
+      &[1],
+      request(0),
+    ) {
+      Ok(_) => panic!("Batch without versions must be rejected"),
+      Err(ServiceError::Invalid(violations)) => assert_eq!(
+        violations
+          .iter()
+          .map(|v| v.field.as_str())
+          .collect::<Vec<&str>>(),
+        vec!["product_updates[0]", "sku_updates[0]"]
+      ),
+      Err(_) => panic!("Missing versions must be reported as violations"),
+    }
+  }
+}
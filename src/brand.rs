@@ -0,0 +1,203 @@
+// Copyright (C) 2020 Peter Mezei
+//
+// This file is part of Gardenzilla.
+//
+// Gardenzilla is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// Gardenzilla is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Gardenzilla.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::prelude::*;
+use chrono::prelude::*;
+use packman::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Brand {
+  /// Brand ID
+  pub brand_id: u32,
+  /// Brand name
+  pub name: String,
+  /// Brand description
+  pub description: String,
+  /// Created by UID
+  pub created_by: u32,
+  /// Created at
+  pub created_at: DateTime<Utc>,
+}
+
+impl Brand {
+  /// Create new brand object
+  pub fn new(brand_id: u32, name: String, description: String, created_by: u32) -> Self {
+    Self {
+      brand_id,
+      name,
+      description,
+      created_by,
+      created_at: Utc::now(),
+    }
+  }
+  /// Update brand data
+  pub fn update(&mut self, name: String, description: String) -> &Self {
+    self.name = name;
+    self.description = description;
+    self
+  }
+}
+
+impl Default for Brand {
+  fn default() -> Self {
+    Self {
+      brand_id: 0,
+      name: String::default(),
+      description: String::default(),
+      created_by: 0,
+      created_at: Utc::now(),
+    }
+  }
+}
+
+impl TryFrom for Brand {
+  type TryFrom = Brand;
+}
+
+impl VecPackMember for Brand {
+  type Out = u32;
+  fn get_id(&self) -> &Self::Out {
+    &self.brand_id
+  }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Manufacturer {
+  /// Manufacturer ID
+  pub manufacturer_id: u32,
+  /// Manufacturer name
+  pub name: String,
+  /// Manufacturer description
+  pub description: String,
+  /// Created by UID
+  pub created_by: u32,
+  /// Created at
+  pub created_at: DateTime<Utc>,
+}
+
+impl Manufacturer {
+  /// Create new manufacturer object
+  pub fn new(manufacturer_id: u32, name: String, description: String, created_by: u32) -> Self {
+    Self {
+      manufacturer_id,
+      name,
+      description,
+      created_by,
+      created_at: Utc::now(),
+    }
+  }
+  /// Update manufacturer data
+  pub fn update(&mut self, name: String, description: String) -> &Self {
+    self.name = name;
+    self.description = description;
+    self
+  }
+}
+
+impl Default for Manufacturer {
+  fn default() -> Self {
+    Self {
+      manufacturer_id: 0,
+      name: String::default(),
+      description: String::default(),
+      created_by: 0,
+      created_at: Utc::now(),
+    }
+  }
+}
+
+impl TryFrom for Manufacturer {
+  type TryFrom = Manufacturer;
+}
+
+impl VecPackMember for Manufacturer {
+  type Out = u32;
+  fn get_id(&self) -> &Self::Out {
+    &self.manufacturer_id
+  }
+}
+
+/// Check brand or manufacturer name
+/// Name must not be empty, nor taken by an other one (case-insensitive)
+/// noun is the Hungarian name of the kind, e.g. márka
+/// Returns the trimmed name
+pub fn check_name<'a, I>(name: &str, others: I, noun: &str) -> ServiceResult<String>
+where
+  I: Iterator<Item = &'a str>,
+{
+  let name = name.trim().to_string();
+  if name.is_empty() {
+    return Err(ServiceError::bad_request(&format!(
+      "A {} neve nem lehet üres!",
+      noun
+    )));
+  }
+  let lowercase = name.to_lowercase();
+  if others.map(|o| o.to_lowercase()).any(|o| o == lowercase) {
+    return Err(ServiceError::already_exist(&format!(
+      "A megadott {} már létezik!",
+      noun
+    )));
+  }
+  Ok(name)
+}
+
+/// Check that no product refers to the brand or manufacturer
+/// references are the related IDs of every product
+pub fn check_unreferenced<I>(id: u32, mut references: I, noun: &str) -> ServiceResult<()>
+where
+  I: Iterator<Item = Option<u32>>,
+{
+  match references.any(|r| r == Some(id)) {
+    true => Err(ServiceError::bad_request(&format!(
+      "A {} nem törölhető, mert termék tartozik hozzá!",
+      noun
+    ))),
+    false => Ok(()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_check_name() {
+    let others = ["Gardena", "Bosch"];
+    assert_eq!(
+      check_name(" Fiskars ", others.iter().copied(), "márka").unwrap(),
+      "Fiskars"
+    );
+    // Case-insensitive uniqueness
+    assert!(matches!(
+      check_name("GARDENA", others.iter().copied(), "márka"),
+      Err(ServiceError::AlreadyExists(_))
+    ));
+    assert!(matches!(
+      check_name("  ", others.iter().copied(), "márka"),
+      Err(ServiceError::BadRequest(_))
+    ));
+  }
+
+  #[test]
+  fn test_check_unreferenced() {
+    let references = [None, Some(2), Some(3)];
+    assert!(check_unreferenced(1, references.iter().copied(), "gyártó").is_ok());
+    assert!(check_unreferenced(2, references.iter().copied(), "gyártó").is_err());
+  }
+}
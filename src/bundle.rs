@@ -0,0 +1,122 @@
+// Copyright (C) 2020 Peter Mezei
+//
+// This file is part of Gardenzilla.
+//
+// Gardenzilla is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// Gardenzilla is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Gardenzilla.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::product::Sku;
+use serde::{Deserialize, Serialize};
+
+/// Component of a bundle SKU
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BundleItem {
+  /// Component SKU ID
+  pub sku: u32,
+  /// Component count in the bundle
+  pub quantity: u32,
+  /// Component display name snapshot
+  pub display_name: String,
+  /// Component discontinued snapshot
+  pub discontinued: bool,
+}
+
+impl std::fmt::Display for BundleItem {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}x {}", self.quantity, self.display_name)
+  }
+}
+
+impl BundleItem {
+  /// Create bundle item from its component SKU
+  pub fn new(component: &Sku, quantity: u32) -> Self {
+    Self {
+      sku: component.sku,
+      quantity,
+      display_name: component.display_name.clone(),
+      discontinued: component.discontinued,
+    }
+  }
+}
+
+/// Validate bundle components
+/// Components must exist, cannot be bundles or discontinued
+/// and can occur only once
+pub fn validate<'a, F>(components: &[(u32, u32)], find: F) -> Result<Vec<BundleItem>, String>
+where
+  F: Fn(u32) -> Option<&'a Sku>,
+{
+  if components.is_empty() {
+    return Err("A csomagnak legalább egy összetevője kell legyen!".to_string());
+  }
+  let mut res: Vec<BundleItem> = Vec::new();
+  for (sku, quantity) in components {
+    if *quantity == 0 {
+      return Err(format!("Az összetevő mennyisége nem lehet 0: {}", sku));
+    }
+    if res.iter().any(|i| i.sku == *sku) {
+      return Err(format!("Ismétlődő összetevő: {}", sku));
+    }
+    let component = find(*sku).ok_or_else(|| format!("Az összetevő nem létezik: {}", sku))?;
+    if component.is_bundle() {
+      return Err(format!(
+        "Csomag nem lehet egy másik csomag összetevője: {}",
+        sku
+      ));
+    }
+    if component.discontinued {
+      return Err(format!("Az összetevő kivezetett: {}", sku));
+    }
+    if component.archived {
+      return Err(format!("Az összetevő archivált: {}", sku));
+    }
+    res.push(BundleItem::new(component, *quantity));
+  }
+  Ok(res)
+}
+
+/// Bundle packaging display based on its components
+pub fn display_packaging(items: &[BundleItem]) -> String {
+  items
+    .iter()
+    .map(|i| i.to_string())
+    .collect::<Vec<String>>()
+    .join(" + ")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::product::Product;
+  use crate::quantity::{Quantity, Unit};
+
+  #[test]
+  fn test_validate() {
+    let pot = Product::new(1, "Cserép".into(), "".into(), Unit::Piece, 1);
+    let soil = Product::new(2, "Virágföld".into(), "".into(), Unit::Milliliter, 1);
+    let skus = [
+      Sku::new(1, 1, &pot, "12cm".into(), Quantity::Simple(1), 1),
+      Sku::new(2, 2, &soil, "".into(), Quantity::Simple(20_000), 1),
+    ];
+    let find = |id: u32| skus.iter().find(|s| s.sku == id);
+    let items = validate(&[(1, 2), (2, 1)], find).unwrap();
+    assert_eq!(
+      display_packaging(&items),
+      "2x Cserép 12cm, 1 db + 1x Virágföld , 20 l"
+    );
+    assert!(validate(&[], find).is_err());
+    assert!(validate(&[(1, 0)], find).is_err());
+    assert!(validate(&[(1, 1), (1, 2)], find).is_err());
+    assert!(validate(&[(3, 1)], find).is_err());
+  }
+}
@@ -0,0 +1,157 @@
+// Copyright (C) 2020 Peter Mezei
+//
+// This file is part of Gardenzilla.
+//
+// Gardenzilla is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// Gardenzilla is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Gardenzilla.  If not, see <http://www.gnu.org/licenses/>.
+
+use chrono::prelude::*;
+use packman::*;
+use serde::{Deserialize, Serialize};
+
+/// Category tree node
+/// Root nodes have no parent
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Category {
+  /// Category ID
+  pub category_id: u32,
+  /// Parent category ID
+  /// None if its a root category
+  pub parent: Option<u32>,
+  /// Category name
+  pub name: String,
+  /// Created by UID
+  pub created_by: u32,
+  /// Created at
+  pub created_at: DateTime<Utc>,
+}
+
+impl Category {
+  /// Create new category object
+  pub fn new(category_id: u32, parent: Option<u32>, name: String, created_by: u32) -> Self {
+    Self {
+      category_id,
+      parent,
+      name,
+      created_by,
+      created_at: Utc::now(),
+    }
+  }
+  /// Rename category
+  pub fn rename(&mut self, name: String) -> &Self {
+    self.name = name;
+    self
+  }
+  /// Move category under a new parent
+  /// None means move to root
+  pub fn set_parent(&mut self, parent: Option<u32>) -> &Self {
+    self.parent = parent;
+    self
+  }
+}
+
+impl Default for Category {
+  fn default() -> Self {
+    Self {
+      category_id: 0,
+      parent: None,
+      name: String::default(),
+      created_by: 0,
+      created_at: Utc::now(),
+    }
+  }
+}
+
+impl TryFrom for Category {
+  type TryFrom = Category;
+}
+
+impl VecPackMember for Category {
+  type Out = u32;
+  fn get_id(&self) -> &Self::Out {
+    &self.category_id
+  }
+}
+
+/// Collect the given category ID and all of its
+/// descendant IDs from a flat category list.
+/// Result is in breadth-first order, starting with the given ID.
+pub fn subtree_ids<'a, I>(categories: I, category_id: u32) -> Vec<u32>
+where
+  I: Iterator<Item = &'a Category> + Clone,
+{
+  let mut res = vec![category_id];
+  let mut cursor = 0;
+  while cursor < res.len() {
+    let parent = res[cursor];
+    categories
+      .clone()
+      .filter(|c| c.parent == Some(parent))
+      .for_each(|c| {
+        // Guard against corrupt data with a cycle in it
+        if !res.contains(&c.category_id) {
+          res.push(c.category_id)
+        }
+      });
+    cursor += 1;
+  }
+  res
+}
+
+/// Check if a category has at least one child
+pub fn has_child<'a, I>(mut categories: I, category_id: u32) -> bool
+where
+  I: Iterator<Item = &'a Category>,
+{
+  categories.any(|c| c.parent == Some(category_id))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn tree() -> Vec<Category> {
+    // A
+    //   AA
+    //     AAA
+    //     AAB
+    //   AB
+    // B
+    vec![
+      Category::new(1, None, "A".into(), 0),
+      Category::new(2, Some(1), "AA".into(), 0),
+      Category::new(3, Some(2), "AAA".into(), 0),
+      Category::new(4, Some(2), "AAB".into(), 0),
+      Category::new(5, Some(1), "AB".into(), 0),
+      Category::new(6, None, "B".into(), 0),
+    ]
+  }
+
+  #[test]
+  fn test_subtree_ids() {
+    let tree = tree();
+    assert_eq!(subtree_ids(tree.iter(), 1), vec![1, 2, 5, 3, 4]);
+    assert_eq!(subtree_ids(tree.iter(), 2), vec![2, 3, 4]);
+    assert_eq!(subtree_ids(tree.iter(), 3), vec![3]);
+    assert_eq!(subtree_ids(tree.iter(), 6), vec![6]);
+  }
+
+  #[test]
+  fn test_has_child() {
+    let tree = tree();
+    assert!(has_child(tree.iter(), 1));
+    assert!(has_child(tree.iter(), 2));
+    assert!(!has_child(tree.iter(), 3));
+    assert!(!has_child(tree.iter(), 6));
+  }
+}
@@ -1 +1,419 @@
+// Copyright (C) 2020 Peter Mezei
+//
+// This file is part of Gardenzilla.
+//
+// Gardenzilla is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// Gardenzilla is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Gardenzilla.  If not, see <http://www.gnu.org/licenses/>.
 
+use crate::format::FormatProfile;
+use crate::prelude::*;
+use crate::quantity::{Decimal, Quantity, Unit};
+
+/// Physical dimension of a measure unit
+/// Only units of the same dimension can be converted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dimension {
+  Piece,
+  Pair,
+  Set,
+  Length,
+  Mass,
+  Volume,
+  Area,
+}
+
+/// Unit of measure a quantity can be expressed in
+/// Every measure unit is a power of ten multiple
+/// of the smallest unit of its dimension
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MeasureUnit {
+  Piece,
+  Pair,
+  Set,
+  Millimeter,
+  Centimeter,
+  Meter,
+  RunningMeter,
+  Gram,
+  Kilogram,
+  Tonne,
+  Milliliter,
+  Centiliter,
+  Liter,
+  CubicMeter,
+  SquareMeter,
+}
+
+impl std::fmt::Display for MeasureUnit {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match &self {
+      MeasureUnit::Piece => write!(f, "db"),
+      MeasureUnit::Pair => write!(f, "pár"),
+      MeasureUnit::Set => write!(f, "szett"),
+      MeasureUnit::Millimeter => write!(f, "mm"),
+      MeasureUnit::Centimeter => write!(f, "cm"),
+      MeasureUnit::Meter => write!(f, "m"),
+      MeasureUnit::RunningMeter => write!(f, "fm"),
+      MeasureUnit::Gram => write!(f, "g"),
+      MeasureUnit::Kilogram => write!(f, "kg"),
+      MeasureUnit::Tonne => write!(f, "t"),
+      MeasureUnit::Milliliter => write!(f, "ml"),
+      MeasureUnit::Centiliter => write!(f, "cl"),
+      MeasureUnit::Liter => write!(f, "l"),
+      MeasureUnit::CubicMeter => write!(f, "m³"),
+      MeasureUnit::SquareMeter => write!(f, "m²"),
+    }
+  }
+}
+
+impl From<&Unit> for MeasureUnit {
+  fn from(unit: &Unit) -> Self {
+    match unit {
+      Unit::Piece => MeasureUnit::Piece,
+      Unit::Millimeter => MeasureUnit::Millimeter,
+      Unit::Gram => MeasureUnit::Gram,
+      Unit::Milliliter => MeasureUnit::Milliliter,
+      Unit::SquareMeter => MeasureUnit::SquareMeter,
+      Unit::CubicMeter => MeasureUnit::CubicMeter,
+      Unit::RunningMeter => MeasureUnit::RunningMeter,
+      Unit::Pair => MeasureUnit::Pair,
+      Unit::Set => MeasureUnit::Set,
+    }
+  }
+}
+
+impl MeasureUnit {
+  pub fn try_from_str(from: &str) -> ServiceResult<MeasureUnit> {
+    let from = from.trim();
+    let res = match from.to_lowercase().as_str() {
+      "piece" | "db" => MeasureUnit::Piece,
+      "pair" | "pár" => MeasureUnit::Pair,
+      "set" | "szett" => MeasureUnit::Set,
+      "millimeter" | "mm" => MeasureUnit::Millimeter,
+      "centimeter" | "cm" => MeasureUnit::Centimeter,
+      "meter" | "m" => MeasureUnit::Meter,
+      "running_meter" | "fm" => MeasureUnit::RunningMeter,
+      "gram" | "gr" | "g" => MeasureUnit::Gram,
+      "kilogram" | "kg" => MeasureUnit::Kilogram,
+      "tonne" | "t" => MeasureUnit::Tonne,
+      "milliliter" | "ml" => MeasureUnit::Milliliter,
+      "centiliter" | "cl" => MeasureUnit::Centiliter,
+      "liter" | "l" => MeasureUnit::Liter,
+      "cubic_meter" | "m3" | "m³" => MeasureUnit::CubicMeter,
+      "square_meter" | "m2" | "m²" => MeasureUnit::SquareMeter,
+      _ => {
+        return Err(ServiceError::bad_request(&format!(
+          "Ismeretlen mértékegység: {}",
+          from
+        )))
+      }
+    };
+    Ok(res)
+  }
+  /// Dimension of the unit
+  pub fn dimension(&self) -> Dimension {
+    match self {
+      MeasureUnit::Piece => Dimension::Piece,
+      MeasureUnit::Pair => Dimension::Pair,
+      MeasureUnit::Set => Dimension::Set,
+      MeasureUnit::Millimeter
+      | MeasureUnit::Centimeter
+      | MeasureUnit::Meter
+      | MeasureUnit::RunningMeter => Dimension::Length,
+      MeasureUnit::Gram | MeasureUnit::Kilogram | MeasureUnit::Tonne => Dimension::Mass,
+      MeasureUnit::Milliliter
+      | MeasureUnit::Centiliter
+      | MeasureUnit::Liter
+      | MeasureUnit::CubicMeter => Dimension::Volume,
+      MeasureUnit::SquareMeter => Dimension::Area,
+    }
+  }
+  /// One of self is 10^exponent of the smallest unit
+  /// of its dimension
+  pub fn exponent(&self) -> u32 {
+    match self {
+      MeasureUnit::Piece
+      | MeasureUnit::Pair
+      | MeasureUnit::Set
+      | MeasureUnit::Millimeter
+      | MeasureUnit::Gram
+      | MeasureUnit::Milliliter
+      | MeasureUnit::SquareMeter => 0,
+      MeasureUnit::Centimeter | MeasureUnit::Centiliter => 1,
+      MeasureUnit::Meter
+      | MeasureUnit::RunningMeter
+      | MeasureUnit::Kilogram
+      | MeasureUnit::Liter => 3,
+      MeasureUnit::Tonne | MeasureUnit::CubicMeter => 6,
+    }
+  }
+}
+
+/// Convert an amount between two measure units of the same dimension
+/// Conversion is exact, as every factor is a power of ten
+pub fn convert(amount: Decimal, from: MeasureUnit, to: MeasureUnit) -> ServiceResult<Decimal> {
+  if from.dimension() != to.dimension() {
+    return Err(ServiceError::bad_request(&format!(
+      "A(z) {} nem váltható át {} mértékegységre!",
+      from, to
+    )));
+  }
+  match from.exponent() >= to.exponent() {
+    true => amount
+      .shift_left(from.exponent() - to.exponent())
+      .ok_or_else(|| ServiceError::bad_request("Az átváltott mennyiség túl nagy!")),
+    false => Ok(amount.shift_right(to.exponent() - from.exponent())),
+  }
+}
+
+/// Quantity expressed in a given measure unit
+/// Complex quantities keep their multiplier, e.g. 3x0.5 kg
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConvertedQuantity {
+  pub multiplier: Option<u32>,
+  pub amount: Decimal,
+  pub unit: MeasureUnit,
+}
+
+impl ConvertedQuantity {
+  /// Total amount, multiplier included
+  pub fn total(&self) -> ServiceResult<Decimal> {
+    match self.multiplier {
+      Some(m) => self
+        .amount
+        .checked_mul(m as u64)
+        .ok_or_else(|| ServiceError::bad_request("Az átváltott mennyiség túl nagy!")),
+      None => Ok(self.amount),
+    }
+  }
+  /// Quantity part without the unit
+  pub fn quantity(&self) -> String {
+    match self.multiplier {
+      Some(m) => format!("{}x{}", m, self.amount),
+      None => self.amount.to_string(),
+    }
+  }
+}
+
+impl ConvertedQuantity {
+  /// Display using the given format profile, e.g. 3x0,5 kg
+  pub fn display_with(&self, profile: &FormatProfile) -> String {
+    let amount = profile.number(&self.amount.to_string());
+    match self.multiplier {
+      Some(m) => format!(
+        "{}x{} {}",
+        profile.number(&m.to_string()),
+        amount,
+        profile.label(self.unit)
+      ),
+      None => format!("{} {}", amount, profile.label(self.unit)),
+    }
+  }
+}
+
+impl std::fmt::Display for ConvertedQuantity {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{} {}", self.quantity(), self.unit)
+  }
+}
+
+/// Express a quantity stored in its base unit in the target measure unit
+pub fn express_in(
+  quantity: &Quantity,
+  unit: &Unit,
+  target: MeasureUnit,
+) -> ServiceResult<ConvertedQuantity> {
+  let from = MeasureUnit::from(unit);
+  let (multiplier, amount) = match quantity.migrate()? {
+    Quantity::Simple(q) => (None, Decimal::from(q)),
+    Quantity::Complex(m, q) => (Some(m), Decimal::from(q)),
+    Quantity::Decimal(d) => (None, d),
+    Quantity::Float(_) => return Err(ServiceError::internal_error("This should never happen")),
+  };
+  Ok(ConvertedQuantity {
+    multiplier,
+    amount: convert(amount, from, target)?,
+    unit: target,
+  })
+}
+
+/// Reference unit of the comparison (unit) price of a base unit,
+/// e.g. price per kg for gram products
+pub fn reference_unit(unit: &Unit) -> MeasureUnit {
+  match unit {
+    Unit::Piece => MeasureUnit::Piece,
+    Unit::Millimeter => MeasureUnit::Meter,
+    Unit::Gram => MeasureUnit::Kilogram,
+    Unit::Milliliter => MeasureUnit::Liter,
+    Unit::SquareMeter => MeasureUnit::SquareMeter,
+    Unit::CubicMeter => MeasureUnit::CubicMeter,
+    Unit::RunningMeter => MeasureUnit::RunningMeter,
+    Unit::Pair => MeasureUnit::Pair,
+    Unit::Set => MeasureUnit::Set,
+  }
+}
+
+/// Total content of a quantity in the reference unit of its base unit,
+/// e.g. 3x500 g is 1.5 kg
+pub fn comparison_quantity(quantity: &Quantity, unit: &Unit) -> ServiceResult<ConvertedQuantity> {
+  let res = express_in(quantity, unit, reference_unit(unit))?;
+  Ok(ConvertedQuantity {
+    multiplier: None,
+    amount: res.total()?,
+    unit: res.unit,
+  })
+}
+
+/// Gross price of one reference unit, rounded to integer
+pub fn unit_price(gross_price: u32, quantity: &Quantity, unit: &Unit) -> ServiceResult<u32> {
+  let content = comparison_quantity(quantity, unit)?;
+  match content.amount.divide(gross_price as u64) {
+    Some(res) if res <= u32::MAX as u64 => Ok(res as u32),
+    _ => Err(ServiceError::bad_request(
+      "Az egységár nem számítható ki, a SKU mennyisége 0 vagy túl kicsi!",
+    )),
+  }
+}
+
+/// Formatted unit price using the given format profile, e.g. 3 980 Ft/kg
+pub fn display_unit_price_with(unit_price: u32, unit: &Unit, profile: &FormatProfile) -> String {
+  format!(
+    "{} Ft/{}",
+    profile.number(&unit_price.to_string()),
+    profile.label(reference_unit(unit))
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Formatted unit price by the default profile, e.g. 3980 Ft/kg
+  fn display_unit_price(unit_price: u32, unit: &Unit) -> String {
+    display_unit_price_with(unit_price, unit, &FormatProfile::default())
+  }
+
+  #[test]
+  fn test_convert() {
+    let d = |s: &str| Decimal::try_from_str(s).unwrap();
+    assert_eq!(
+      convert(d("1.5"), MeasureUnit::Kilogram, MeasureUnit::Gram).unwrap(),
+      d("1500")
+    );
+    assert_eq!(
+      convert(d("250"), MeasureUnit::Gram, MeasureUnit::Kilogram).unwrap(),
+      d("0.25")
+    );
+    assert_eq!(
+      convert(d("2"), MeasureUnit::Tonne, MeasureUnit::Kilogram).unwrap(),
+      d("2000")
+    );
+    assert_eq!(
+      convert(d("75"), MeasureUnit::Centiliter, MeasureUnit::Liter).unwrap(),
+      d("0.75")
+    );
+    assert_eq!(
+      convert(d("12.5"), MeasureUnit::Centimeter, MeasureUnit::Millimeter).unwrap(),
+      d("125")
+    );
+    assert!(convert(d("1"), MeasureUnit::Liter, MeasureUnit::Kilogram).is_err());
+    assert!(convert(d("1"), MeasureUnit::Piece, MeasureUnit::Gram).is_err());
+    assert_eq!(
+      convert(d("0.5"), MeasureUnit::CubicMeter, MeasureUnit::Liter).unwrap(),
+      d("500")
+    );
+    assert_eq!(
+      convert(d("25"), MeasureUnit::RunningMeter, MeasureUnit::Centimeter).unwrap(),
+      d("2500")
+    );
+    assert_eq!(
+      convert(d("2"), MeasureUnit::SquareMeter, MeasureUnit::SquareMeter).unwrap(),
+      d("2")
+    );
+    assert!(convert(d("1"), MeasureUnit::Pair, MeasureUnit::Piece).is_err());
+    assert!(convert(d("1"), MeasureUnit::SquareMeter, MeasureUnit::Meter).is_err());
+  }
+
+  #[test]
+  fn test_express_in() {
+    let kg = express_in(
+      &Quantity::Complex(3, 500),
+      &Unit::Gram,
+      MeasureUnit::Kilogram,
+    )
+    .unwrap();
+    assert_eq!(kg.to_string(), "3x0.5 kg");
+    assert_eq!(kg.total().unwrap().to_string(), "1.5");
+    assert_eq!(kg.display_with(&FormatProfile::hungarian()), "3x0,5 kg");
+    let m = express_in(
+      &Quantity::Simple(1005),
+      &Unit::Millimeter,
+      MeasureUnit::Meter,
+    )
+    .unwrap();
+    assert_eq!(m.to_string(), "1.005 m");
+    let l = express_in(&Quantity::Float(0.5), &Unit::Milliliter, MeasureUnit::Liter).unwrap();
+    assert_eq!(l.to_string(), "0.0005 l");
+    assert!(express_in(&Quantity::Simple(1), &Unit::Piece, MeasureUnit::Gram).is_err());
+  }
+
+  #[test]
+  fn test_measure_unit_try_from_str() {
+    assert_eq!(
+      MeasureUnit::try_from_str(" KG ").unwrap(),
+      MeasureUnit::Kilogram
+    );
+    assert_eq!(
+      MeasureUnit::try_from_str("cl").unwrap(),
+      MeasureUnit::Centiliter
+    );
+    assert_eq!(MeasureUnit::try_from_str("db").unwrap(), MeasureUnit::Piece);
+    assert_eq!(
+      MeasureUnit::try_from_str("m²").unwrap(),
+      MeasureUnit::SquareMeter
+    );
+    assert_eq!(
+      MeasureUnit::try_from_str("M3").unwrap(),
+      MeasureUnit::CubicMeter
+    );
+    assert_eq!(MeasureUnit::try_from_str("pár").unwrap(), MeasureUnit::Pair);
+    assert!(MeasureUnit::try_from_str("lbs").is_err());
+  }
+
+  #[test]
+  fn test_unit_price() {
+    let content = |q: Quantity, u: Unit| comparison_quantity(&q, &u).unwrap().to_string();
+    assert_eq!(content(Quantity::Complex(3, 500), Unit::Gram), "1.5 kg");
+    assert_eq!(content(Quantity::Simple(750), Unit::Milliliter), "0.75 l");
+    assert_eq!(content(Quantity::Simple(25), Unit::SquareMeter), "25 m²");
+    assert_eq!(content(Quantity::Complex(4, 1), Unit::Piece), "4 db");
+    assert_eq!(
+      unit_price(5970, &Quantity::Complex(3, 500), &Unit::Gram).unwrap(),
+      3980
+    );
+    assert_eq!(
+      unit_price(999, &Quantity::Simple(750), &Unit::Milliliter).unwrap(),
+      1332
+    );
+    assert_eq!(
+      unit_price(1000, &Quantity::Simple(3), &Unit::Piece).unwrap(),
+      333
+    );
+    assert!(unit_price(1000, &Quantity::Simple(0), &Unit::Gram).is_err());
+    assert_eq!(display_unit_price(3980, &Unit::Gram), "3980 Ft/kg");
+    assert_eq!(
+      display_unit_price_with(3980, &Unit::Gram, &FormatProfile::hungarian()),
+      "3 980 Ft/kg"
+    );
+  }
+}
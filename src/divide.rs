@@ -0,0 +1,276 @@
+// Copyright (C) 2020 Peter Mezei
+//
+// This file is part of Gardenzilla.
+//
+// Gardenzilla is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// Gardenzilla is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Gardenzilla.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::prelude::*;
+use crate::quantity::{Decimal, Quantity};
+use serde::{Deserialize, Serialize};
+
+/// Allowed fraction of the full SKU content, e.g. 1/2
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Fraction {
+  pub numerator: u32,
+  pub denominator: u32,
+}
+
+impl Fraction {
+  /// Parse fraction, e.g. 1/2 or 3/4
+  pub fn try_from_str(s: &str) -> ServiceResult<Fraction> {
+    let error = || ServiceError::bad_request(&format!("Hibás arány: '{}'. Pl.: 1/2", s.trim()));
+    let (numerator, denominator) = s.trim().split_once('/').ok_or_else(error)?;
+    let numerator = numerator.trim().parse::<u32>().map_err(|_| error())?;
+    let denominator = denominator.trim().parse::<u32>().map_err(|_| error())?;
+    if numerator == 0 || numerator >= denominator {
+      return Err(ServiceError::bad_request(&format!(
+        "Az aránynak 0 és 1 közé kell esnie: '{}'",
+        s.trim()
+      )));
+    }
+    Ok(Fraction {
+      numerator,
+      denominator,
+    })
+  }
+  /// Check if two fractions are equal, e.g. 1/2 and 2/4
+  fn same_as(&self, other: &Fraction) -> bool {
+    self.numerator as u64 * other.denominator as u64
+      == other.numerator as u64 * self.denominator as u64
+  }
+  /// Check if amount is exactly this fraction of total
+  fn matches(&self, total: Decimal, amount: Decimal) -> bool {
+    match (
+      amount.checked_mul(self.denominator as u64),
+      total.checked_mul(self.numerator as u64),
+    ) {
+      (Some(a), Some(b)) => a == b,
+      _ => false,
+    }
+  }
+}
+
+impl std::fmt::Display for Fraction {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}/{}", self.numerator, self.denominator)
+  }
+}
+
+/// Rule of splitting an opened SKU
+/// Amounts are in the product unit
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DivideRule {
+  /// Minimum sell step, e.g. 100 g
+  /// Split amounts must be its multiples
+  pub step: Decimal,
+  /// Amount that must remain from the opened SKU
+  pub min_remaining: Decimal,
+  /// Allowed fractions of the full content
+  /// Empty means any multiple of step
+  pub fractions: Vec<Fraction>,
+}
+
+/// Default rule, any whole amount can be split
+impl Default for DivideRule {
+  fn default() -> Self {
+    Self {
+      step: Decimal::from(1),
+      min_remaining: Decimal::default(),
+      fractions: Vec::new(),
+    }
+  }
+}
+
+impl std::fmt::Display for DivideRule {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "lépés: {}, maradék: {}, arányok: [{}]",
+      self.step,
+      self.min_remaining,
+      self
+        .fractions
+        .iter()
+        .map(|f| f.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+    )
+  }
+}
+
+impl DivideRule {
+  /// Validate rule against the SKU quantity
+  pub fn validate(&self, quantity: &Quantity) -> Result<(), String> {
+    let total = quantity.total();
+    if self.step == Decimal::default() {
+      return Err("A minimális lépés nem lehet 0!".to_string());
+    }
+    if self.step > total {
+      return Err(format!(
+        "A minimális lépés ({}) nem lehet nagyobb a teljes mennyiségnél ({})!",
+        self.step, total
+      ));
+    }
+    if self.min_remaining >= total {
+      return Err(format!(
+        "A minimális maradék ({}) kisebb kell legyen a teljes mennyiségnél ({})!",
+        self.min_remaining, total
+      ));
+    }
+    // At least one step must be splittable, keeping the minimum remaining
+    let smallest_split =
+      Quantity::from_total(self.step).checked_add(&Quantity::from_total(self.min_remaining));
+    if smallest_split.is_none_or(|q| q.total() > total) {
+      return Err(format!(
+        "A minimális lépés ({}) és a minimális maradék ({}) együtt nem lehet nagyobb a teljes mennyiségnél ({})!",
+        self.step, self.min_remaining, total
+      ));
+    }
+    for (index, fraction) in self.fractions.iter().enumerate() {
+      if self.fractions[..index].iter().any(|f| f.same_as(fraction)) {
+        return Err(format!("Ismétlődő arány: {}", fraction));
+      }
+    }
+    Ok(())
+  }
+  /// Check split amount against the total SKU content
+  /// Returns the remaining amount
+  pub fn check(&self, total: Decimal, amount: Decimal) -> Result<Decimal, String> {
+    if amount == Decimal::default() {
+      return Err("A kimérendő mennyiség nem lehet 0!".to_string());
+    }
+    let remaining = match total.checked_sub(&amount) {
+      Some(remaining) if remaining != Decimal::default() => remaining,
+      _ => {
+        return Err(format!(
+          "A kimérendő mennyiség ({}) kisebb kell legyen a teljes mennyiségnél ({})!",
+          amount, total
+        ))
+      }
+    };
+    if !amount.is_multiple_of(&self.step) {
+      return Err(format!(
+        "A kimérendő mennyiség ({}) a minimális lépés ({}) többszöröse kell legyen!",
+        amount, self.step
+      ));
+    }
+    if remaining < self.min_remaining {
+      return Err(format!(
+        "A maradék ({}) nem lehet kevesebb a minimális maradéknál ({})!",
+        remaining, self.min_remaining
+      ));
+    }
+    if !self.fractions.is_empty() && !self.fractions.iter().any(|f| f.matches(total, amount)) {
+      return Err(format!(
+        "A kimérendő mennyiség ({}) nem megengedett arány. Lehetséges: {}",
+        amount,
+        self
+          .fractions
+          .iter()
+          .map(|f| f.to_string())
+          .collect::<Vec<String>>()
+          .join(", ")
+      ));
+    }
+    Ok(remaining)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_fraction() {
+    assert_eq!(
+      Fraction::try_from_str(" 3 / 4 ").unwrap(),
+      Fraction {
+        numerator: 3,
+        denominator: 4
+      }
+    );
+    assert!(Fraction::try_from_str("0/2").is_err());
+    assert!(Fraction::try_from_str("2/2").is_err());
+    assert!(Fraction::try_from_str("1/0").is_err());
+    assert!(Fraction::try_from_str("fél").is_err());
+  }
+
+  #[test]
+  fn test_check() {
+    // 25 kg bag sold by 100 g, at least 1 kg must remain
+    let rule = DivideRule {
+      step: Decimal::from(100),
+      min_remaining: Decimal::from(1000),
+      fractions: Vec::new(),
+    };
+    let total = Decimal::from(25000);
+    assert!(rule.validate(&Quantity::Simple(25000)).is_ok());
+    assert_eq!(
+      rule.check(total, Decimal::from(2500)).unwrap(),
+      Decimal::from(22500)
+    );
+    assert!(rule.check(total, Decimal::from(2550)).is_err());
+    assert!(rule.check(total, Decimal::from(24500)).is_err());
+    assert!(rule.check(total, Decimal::from(25000)).is_err());
+    assert!(rule.check(total, Decimal::default()).is_err());
+    // Only halves and quarters
+    let rule = DivideRule {
+      fractions: vec![
+        Fraction::try_from_str("1/2").unwrap(),
+        Fraction::try_from_str("1/4").unwrap(),
+      ],
+      ..DivideRule::default()
+    };
+    assert!(rule.check(total, Decimal::from(12500)).is_ok());
+    assert!(rule.check(total, Decimal::from(6250)).is_ok());
+    assert!(rule.check(total, Decimal::from(18750)).is_err());
+    // Decimal amounts
+    let rule = DivideRule {
+      step: Decimal::try_from_str("0.5").unwrap(),
+      ..DivideRule::default()
+    };
+    assert!(rule
+      .check(Decimal::from(5), Decimal::try_from_str("1.5").unwrap())
+      .is_ok());
+    assert!(rule
+      .check(Decimal::from(5), Decimal::try_from_str("1.2").unwrap())
+      .is_err());
+  }
+
+  #[test]
+  fn test_validate() {
+    let total = Quantity::Simple(1000);
+    assert!(DivideRule::default().validate(&total).is_ok());
+    let rule = |step: u32, min_remaining: u32| DivideRule {
+      step: Decimal::from(step),
+      min_remaining: Decimal::from(min_remaining),
+      fractions: Vec::new(),
+    };
+    assert!(rule(0, 0).validate(&total).is_err());
+    assert!(rule(2000, 0).validate(&total).is_err());
+    assert!(rule(100, 1000).validate(&total).is_err());
+    // No split would keep the minimum remaining
+    assert!(rule(400, 500).validate(&total).is_ok());
+    assert!(rule(600, 500).validate(&total).is_err());
+    assert!(DivideRule::default().validate(&Quantity::Simple(1)).is_ok());
+    let half = Fraction::try_from_str("1/2").unwrap();
+    let quarters = Fraction::try_from_str("2/4").unwrap();
+    assert!(DivideRule {
+      fractions: vec![half, quarters],
+      ..DivideRule::default()
+    }
+    .validate(&total)
+    .is_err());
+  }
+}
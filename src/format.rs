@@ -0,0 +1,172 @@
+// Copyright (C) 2020 Peter Mezei
+//
+// This file is part of Gardenzilla.
+//
+// Gardenzilla is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// Gardenzilla is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Gardenzilla.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::convert::MeasureUnit;
+use crate::prelude::*;
+use crate::quantity::Quantity;
+use thousands::{digits, Separable, SeparatorPolicy};
+
+/// Unit label set
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LabelSet {
+  // db, pár, szett, fm
+  Hungarian,
+  // pcs, pair, set, rm
+  English,
+}
+
+/// Number and unit formatting rules of display strings
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormatProfile {
+  /// Locale code, empty for the default profile
+  pub locale: String,
+  /// Decimal separator, e.g. , or .
+  pub decimal_separator: char,
+  /// Thousands separator, None means no digit grouping
+  pub grouping: Option<String>,
+  /// Unit labels
+  pub labels: LabelSet,
+}
+
+/// Default profile used for the stored display strings
+/// Hungarian labels, decimal point and no digit grouping
+impl Default for FormatProfile {
+  fn default() -> Self {
+    Self {
+      locale: String::default(),
+      decimal_separator: '.',
+      grouping: None,
+      labels: LabelSet::Hungarian,
+    }
+  }
+}
+
+impl FormatProfile {
+  /// Hungarian profile, e.g. 11 000 db or 1,5 kg
+  pub fn hungarian() -> Self {
+    Self {
+      locale: "hu".to_string(),
+      decimal_separator: ',',
+      grouping: Some(" ".to_string()),
+      labels: LabelSet::Hungarian,
+    }
+  }
+  /// English profile, e.g. 11,000 pcs or 1.5 kg
+  pub fn english() -> Self {
+    Self {
+      locale: "en".to_string(),
+      decimal_separator: '.',
+      grouping: Some(",".to_string()),
+      labels: LabelSet::English,
+    }
+  }
+  /// Profile by locale code, e.g. hu, hu-HU or en
+  /// Empty locale means the default profile
+  pub fn try_from_locale(locale: &str) -> ServiceResult<FormatProfile> {
+    let locale = locale.trim().to_lowercase().replace('_', "-");
+    match locale.split('-').next().unwrap_or_default() {
+      "" => Ok(FormatProfile::default()),
+      "hu" => Ok(FormatProfile::hungarian()),
+      "en" => Ok(FormatProfile::english()),
+      _ => Err(ServiceError::bad_request(&format!(
+        "Nem támogatott nyelv: {}. Lehetséges: hu, en",
+        locale
+      ))),
+    }
+  }
+  /// Format a plain number, e.g. 12345.5
+  pub fn number(&self, number: &str) -> String {
+    let (integer, fraction) = match number.split_once('.') {
+      Some((i, f)) => (i, Some(f)),
+      None => (number, None),
+    };
+    let integer = match &self.grouping {
+      Some(separator) => integer.separate_by_policy(SeparatorPolicy {
+        separator,
+        groups: &[3],
+        digits: digits::ASCII_DECIMAL,
+      }),
+      None => integer.to_string(),
+    };
+    match fraction {
+      Some(fraction) => format!("{}{}{}", integer, self.decimal_separator, fraction),
+      None => integer,
+    }
+  }
+  /// Format quantity without unit
+  pub fn quantity(&self, quantity: &Quantity) -> String {
+    match quantity {
+      Quantity::Simple(q) => self.number(&q.to_string()),
+      Quantity::Complex(m, q) => format!(
+        "{}x{}",
+        self.number(&m.to_string()),
+        self.number(&q.to_string())
+      ),
+      Quantity::Decimal(d) => self.number(&d.to_string()),
+      Quantity::Float(f) => self.number(&format!("{:.1}", f)),
+    }
+  }
+  /// Unit label
+  pub fn label(&self, unit: MeasureUnit) -> String {
+    match self.labels {
+      LabelSet::Hungarian => unit.to_string(),
+      LabelSet::English => match unit {
+        MeasureUnit::Piece => "pcs".to_string(),
+        MeasureUnit::Pair => "pair".to_string(),
+        MeasureUnit::Set => "set".to_string(),
+        MeasureUnit::RunningMeter => "rm".to_string(),
+        _ => unit.to_string(),
+      },
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_number() {
+    let default = FormatProfile::default();
+    let hu = FormatProfile::hungarian();
+    let en = FormatProfile::english();
+    assert_eq!(default.number("11000"), "11000");
+    assert_eq!(default.number("1234.5"), "1234.5");
+    assert_eq!(hu.number("11000"), "11 000");
+    assert_eq!(hu.number("1234567.25"), "1 234 567,25");
+    assert_eq!(hu.number("999"), "999");
+    assert_eq!(en.number("11000"), "11,000");
+    assert_eq!(en.number("1234.5"), "1,234.5");
+  }
+
+  #[test]
+  fn test_try_from_locale() {
+    assert_eq!(
+      FormatProfile::try_from_locale("").unwrap(),
+      FormatProfile::default()
+    );
+    assert_eq!(
+      FormatProfile::try_from_locale("hu_HU").unwrap(),
+      FormatProfile::hungarian()
+    );
+    assert_eq!(
+      FormatProfile::try_from_locale("en-GB").unwrap(),
+      FormatProfile::english()
+    );
+    assert!(FormatProfile::try_from_locale("de").is_err());
+  }
+}
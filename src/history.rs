@@ -0,0 +1,259 @@
+// Copyright (C) 2020 Peter Mezei
+//
+// This file is part of Gardenzilla.
+//
+// Gardenzilla is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// Gardenzilla is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Gardenzilla.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::product::{Product, Sku};
+use chrono::prelude::*;
+use packman::*;
+use serde::{Deserialize, Serialize};
+
+/// Append only change history of a single
+/// Product or Sku record
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct History {
+  /// Related product ID or SKU ID
+  pub id: u32,
+  /// Changes in chronological order
+  pub entries: Vec<HistoryEntry>,
+}
+
+impl History {
+  pub fn new(id: u32) -> Self {
+    Self {
+      id,
+      entries: Vec::new(),
+    }
+  }
+  /// Append new entry
+  pub fn push(&mut self, entry: HistoryEntry) -> &Self {
+    self.entries.push(entry);
+    self
+  }
+}
+
+impl TryFrom for History {
+  type TryFrom = History;
+}
+
+impl VecPackMember for History {
+  type Out = u32;
+  fn get_id(&self) -> &Self::Out {
+    &self.id
+  }
+}
+
+/// Next ID to use for a Product or Sku
+/// Deleted records keep their history under their ID,
+/// so IDs found in history are never reused
+pub fn next_id<'a, I, H>(ids: I, history: H) -> u32
+where
+  I: Iterator<Item = u32>,
+  H: Iterator<Item = &'a History>,
+{
+  ids.chain(history.map(|h| h.id)).max().unwrap_or(0) + 1
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HistoryEntry {
+  /// Changed by UID
+  pub changed_by: u32,
+  /// Changed at
+  pub changed_at: DateTime<Utc>,
+  /// RPC name that made the change
+  pub rpc: String,
+  /// Changed fields
+  pub changes: Vec<FieldChange>,
+}
+
+impl HistoryEntry {
+  pub fn new(changed_by: u32, rpc: &str, changes: Vec<FieldChange>) -> Self {
+    Self {
+      changed_by,
+      changed_at: Utc::now(),
+      rpc: rpc.to_string(),
+      changes,
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FieldChange {
+  pub field: String,
+  pub old_value: String,
+  pub new_value: String,
+}
+
+/// Records that can be tracked in history
+/// Returns the tracked fields as name, display value pairs
+pub trait HistoryFields {
+  fn history_fields(&self) -> Vec<(&'static str, String)>;
+}
+
+/// Compare two states of a record and collect the changed fields
+/// None as old means creation, None as new means deletion
+pub fn diff<T: HistoryFields>(old: Option<&T>, new: Option<&T>) -> Vec<FieldChange> {
+  let old_fields = old.map(|o| o.history_fields()).unwrap_or_default();
+  let new_fields = new.map(|n| n.history_fields()).unwrap_or_default();
+  // Both sides have the same fields in the same order,
+  // except the missing side
+  let fields = match old_fields.is_empty() {
+    true => &new_fields,
+    false => &old_fields,
+  };
+  fields
+    .iter()
+    .map(|(field, _)| {
+      let value = |fields: &Vec<(&'static str, String)>| {
+        fields
+          .iter()
+          .find(|(f, _)| f == field)
+          .map(|(_, v)| v.clone())
+          .unwrap_or_default()
+      };
+      FieldChange {
+        field: field.to_string(),
+        old_value: value(&old_fields),
+        new_value: value(&new_fields),
+      }
+    })
+    .filter(|c| c.old_value != c.new_value)
+    .collect()
+}
+
+fn display_option<T: std::fmt::Display>(value: &Option<T>) -> String {
+  match value {
+    Some(v) => v.to_string(),
+    None => String::default(),
+  }
+}
+
+fn display_list<T: std::fmt::Display>(values: &[T]) -> String {
+  values
+    .iter()
+    .map(|v| v.to_string())
+    .collect::<Vec<String>>()
+    .join(", ")
+}
+
+impl HistoryFields for Product {
+  fn history_fields(&self) -> Vec<(&'static str, String)> {
+    vec![
+      ("name", self.name.clone()),
+      ("description", self.description.clone()),
+      ("unit", self.unit.to_string()),
+      ("discontinued", self.discontinued.to_string()),
+      ("perishable", self.perishable.to_string()),
+      ("skus", display_list(&self.skus)),
+      ("categories", display_list(&self.categories)),
+      ("brand", display_option(&self.brand)),
+      ("manufacturer", display_option(&self.manufacturer)),
+      ("archived", self.archived.to_string()),
+      ("attributes", display_list(&self.attributes)),
+      ("tags", display_list(&self.tags)),
+      ("tax_class", display_option(&self.tax_class)),
+    ]
+  }
+}
+
+impl HistoryFields for Sku {
+  fn history_fields(&self) -> Vec<(&'static str, String)> {
+    vec![
+      ("product_id", self.product_id.to_string()),
+      ("parent_name", self.parent_name.clone()),
+      ("sub_name", self.sub_name.clone()),
+      ("display_name", self.display_name.clone()),
+      ("display_packaging", self.display_packaging.clone()),
+      ("unit", self.unit.to_string()),
+      ("quantity", self.quantity.to_string()),
+      ("can_divide", self.can_divide.to_string()),
+      ("discontinued", self.discontinued.to_string()),
+      ("perishable", self.perishable.to_string()),
+      ("images", display_list(&self.images)),
+      ("primary_image", display_option(&self.primary_image)),
+      (
+        "barcodes",
+        display_list(
+          &self
+            .barcodes
+            .iter()
+            .map(|b| b.code.clone())
+            .collect::<Vec<String>>(),
+        ),
+      ),
+      ("archived", self.archived.to_string()),
+      ("attributes", display_list(&self.attributes)),
+      ("tags", display_list(&self.tags)),
+      ("tax_class", display_option(&self.tax_class)),
+      ("bundle", display_list(&self.bundle)),
+      ("packaging", display_list(&self.packaging)),
+      ("divide_rule", self.divide_rule.to_string()),
+    ]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::quantity::Unit;
+
+  #[test]
+  fn test_next_id() {
+    // Record 3 is deleted, only its history remains
+    let history = [History::new(1), History::new(3)];
+    assert_eq!(next_id([1, 2].iter().copied(), history.iter()), 4);
+    assert_eq!(next_id(std::iter::empty(), std::iter::empty()), 1);
+  }
+
+  #[test]
+  fn test_diff() {
+    let old = Product::new(1, "Virágföld".into(), "".into(), Unit::Gram, 1);
+    let mut new = old.clone();
+    // No change
+    assert_eq!(diff(Some(&old), Some(&new)), vec![]);
+    // Field changes
+    new.update("Virágföld 20l".into(), "".into(), Unit::Milliliter);
+    new.set_categories(vec![1, 2]);
+    assert_eq!(
+      diff(Some(&old), Some(&new)),
+      vec![
+        FieldChange {
+          field: "name".into(),
+          old_value: "Virágföld".into(),
+          new_value: "Virágföld 20l".into(),
+        },
+        FieldChange {
+          field: "unit".into(),
+          old_value: "g".into(),
+          new_value: "ml".into(),
+        },
+        FieldChange {
+          field: "categories".into(),
+          old_value: "".into(),
+          new_value: "1, 2".into(),
+        },
+      ]
+    );
+    // Creation contains every non empty field
+    let created = diff(None, Some(&old));
+    assert!(created
+      .iter()
+      .any(|c| c.field == "name" && c.old_value.is_empty() && c.new_value == "Virágföld"));
+    assert!(created.iter().all(|c| c.old_value.is_empty()));
+    // Deletion
+    let deleted = diff(Some(&old), None);
+    assert!(deleted.iter().all(|c| c.new_value.is_empty()));
+  }
+}
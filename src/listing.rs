@@ -0,0 +1,336 @@
+// Copyright (C) 2020 Peter Mezei
+//
+// This file is part of Gardenzilla.
+//
+// Gardenzilla is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// Gardenzilla is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Gardenzilla.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::prelude::*;
+use crate::product::{Product, Sku};
+use crate::quantity::Unit;
+use crate::search;
+use chrono::prelude::*;
+
+/// Page size if not set
+pub const DEFAULT_LIMIT: usize = 50;
+/// Maximum page size
+pub const MAX_LIMIT: usize = 500;
+
+/// Listing order
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortBy {
+  Id,
+  Name,
+  CreatedAt,
+}
+
+impl SortBy {
+  pub fn try_from_str(s: &str) -> ServiceResult<SortBy> {
+    match s.trim() {
+      "" | "id" => Ok(SortBy::Id),
+      "name" => Ok(SortBy::Name),
+      "created_at" => Ok(SortBy::CreatedAt),
+      _ => Err(ServiceError::bad_request(&format!(
+        "Hibás rendezés: {}. Lehetséges: id, name, created_at",
+        s
+      ))),
+    }
+  }
+}
+
+/// Sort key of a listed record
+/// The ID makes every key unique, so a key can be used as a cursor
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Key {
+  Id(u32),
+  // Normalized name and ID
+  Name(String, u32),
+  // Creation time in microseconds and ID
+  CreatedAt(i64, u32),
+}
+
+impl Key {
+  /// Encode key as an opaque cursor string
+  pub fn to_cursor(&self) -> String {
+    match self {
+      Key::Id(id) => format!("id:{}", id),
+      Key::Name(name, id) => format!(
+        "name:{}:{}",
+        name
+          .bytes()
+          .map(|b| format!("{:02x}", b))
+          .collect::<String>(),
+        id
+      ),
+      Key::CreatedAt(time, id) => format!("created_at:{}:{}", time, id),
+    }
+  }
+  /// Decode cursor, it must belong to the same ordering
+  pub fn try_from_cursor(cursor: &str, sort: SortBy) -> ServiceResult<Key> {
+    let error = || ServiceError::bad_request(&format!("Hibás lapozási kurzor: {}", cursor));
+    let parts = cursor.split(':').collect::<Vec<&str>>();
+    let id = parts
+      .last()
+      .and_then(|id| id.parse::<u32>().ok())
+      .ok_or_else(error)?;
+    match (sort, parts.as_slice()) {
+      (SortBy::Id, ["id", _]) => Ok(Key::Id(id)),
+      (SortBy::Name, ["name", name, _]) => {
+        if !name.len().is_multiple_of(2) {
+          return Err(error());
+        }
+        let bytes = (0..name.len())
+          .step_by(2)
+          .map(|i| u8::from_str_radix(&name[i..i + 2], 16).map_err(|_| error()))
+          .collect::<ServiceResult<Vec<u8>>>()?;
+        let name = String::from_utf8(bytes).map_err(|_| error())?;
+        Ok(Key::Name(name, id))
+      }
+      (SortBy::CreatedAt, ["created_at", time, _]) => {
+        let time = time.parse::<i64>().map_err(|_| error())?;
+        Ok(Key::CreatedAt(time, id))
+      }
+      _ => Err(error()),
+    }
+  }
+}
+
+/// Common listed data of products and SKUs
+pub struct Record<'a> {
+  pub id: u32,
+  pub product_id: u32,
+  pub name: &'a str,
+  pub unit: &'a Unit,
+  pub discontinued: bool,
+  pub perishable: bool,
+  pub archived: bool,
+  pub created_by: u32,
+  pub created_at: DateTime<Utc>,
+}
+
+impl<'a> Record<'a> {
+  /// Sort key by the given ordering
+  pub fn key(&self, sort: SortBy) -> Key {
+    match sort {
+      SortBy::Id => Key::Id(self.id),
+      SortBy::Name => Key::Name(search::normalize(self.name), self.id),
+      SortBy::CreatedAt => Key::CreatedAt(self.created_at.timestamp_micros(), self.id),
+    }
+  }
+}
+
+/// Listable object
+pub trait Listed {
+  fn record(&self) -> Record<'_>;
+}
+
+impl Listed for Product {
+  fn record(&self) -> Record<'_> {
+    Record {
+      id: self.product_id,
+      product_id: self.product_id,
+      name: &self.name,
+      unit: &self.unit,
+      discontinued: self.discontinued,
+      perishable: self.perishable,
+      archived: self.archived,
+      created_by: self.created_by,
+      created_at: self.created_at,
+    }
+  }
+}
+
+impl Listed for Sku {
+  fn record(&self) -> Record<'_> {
+    Record {
+      id: self.sku,
+      product_id: self.product_id,
+      name: &self.display_name,
+      unit: &self.unit,
+      discontinued: self.discontinued,
+      perishable: self.perishable,
+      archived: self.archived,
+      created_by: self.created_by,
+      created_at: self.created_at,
+    }
+  }
+}
+
+/// Listing filter
+/// None means no filtering by that field
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+  pub discontinued: Option<bool>,
+  pub perishable: Option<bool>,
+  pub unit: Option<Unit>,
+  pub created_by: Option<u32>,
+  // Inclusive
+  pub created_from: Option<DateTime<Utc>>,
+  // Exclusive
+  pub created_to: Option<DateTime<Utc>>,
+  pub product_id: Option<u32>,
+  pub include_archived: bool,
+}
+
+impl Filter {
+  /// Check if record matches every set filter
+  pub fn matches(&self, r: &Record) -> bool {
+    self.discontinued.is_none_or(|d| r.discontinued == d)
+      && self.perishable.is_none_or(|p| r.perishable == p)
+      && self.unit.as_ref().is_none_or(|u| r.unit == u)
+      && self.created_by.is_none_or(|c| r.created_by == c)
+      && self.created_from.is_none_or(|f| r.created_at >= f)
+      && self.created_to.is_none_or(|t| r.created_at < t)
+      && self.product_id.is_none_or(|p| r.product_id == p)
+      && (self.include_archived || !r.archived)
+  }
+}
+
+/// Listing request
+#[derive(Debug, Clone)]
+pub struct Query {
+  pub sort: SortBy,
+  pub descending: bool,
+  pub filter: Filter,
+  /// Key of the last item of the previous page
+  pub cursor: Option<Key>,
+  pub limit: usize,
+}
+
+/// One page of a listing
+pub struct Page<T> {
+  pub items: Vec<T>,
+  /// Cursor of the next page, None if this is the last one
+  pub next_cursor: Option<String>,
+  /// Number of all the matching items
+  pub total: usize,
+}
+
+/// Filter, sort and paginate objects
+pub fn page<'a, T, I>(objects: I, query: &Query) -> Page<T>
+where
+  T: Listed + Clone + 'a,
+  I: Iterator<Item = &'a T>,
+{
+  let mut matching = objects
+    .filter(|o| query.filter.matches(&o.record()))
+    .map(|o| (o.record().key(query.sort), o))
+    .collect::<Vec<(Key, &T)>>();
+  matching.sort_by(|a, b| match query.descending {
+    true => b.0.cmp(&a.0),
+    false => a.0.cmp(&b.0),
+  });
+  let total = matching.len();
+  // Skip items up to the cursor
+  let start = match &query.cursor {
+    Some(cursor) => matching
+      .iter()
+      .position(|(key, _)| match query.descending {
+        true => key < cursor,
+        false => key > cursor,
+      })
+      .unwrap_or(total),
+    None => 0,
+  };
+  let end = total.min(start.saturating_add(query.limit));
+  let next_cursor = match end < total && end > start {
+    true => Some(matching[end - 1].0.to_cursor()),
+    false => None,
+  };
+  Page {
+    items: matching[start..end]
+      .iter()
+      .map(|(_, o)| (*o).clone())
+      .collect(),
+    next_cursor,
+    total,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn products() -> Vec<Product> {
+    ["Virágföld", "Ágyás", "Kerti tömlő", "ásó", "Műtrágya"]
+      .iter()
+      .enumerate()
+      .map(|(i, name)| {
+        let mut p = Product::new(i as u32 + 1, name.to_string(), "".into(), Unit::Gram, 1);
+        p.discontinued = i % 2 == 1;
+        p
+      })
+      .collect()
+  }
+
+  fn query(sort: SortBy, descending: bool, limit: usize) -> Query {
+    Query {
+      sort,
+      descending,
+      filter: Filter::default(),
+      cursor: None,
+      limit,
+    }
+  }
+
+  fn ids(page: &Page<Product>) -> Vec<u32> {
+    page.items.iter().map(|p| p.product_id).collect()
+  }
+
+  #[test]
+  fn test_page_cursor() {
+    let products = products();
+    let mut q = query(SortBy::Name, false, 2);
+    let mut res = Vec::new();
+    loop {
+      let current = page(products.iter(), &q);
+      assert_eq!(current.total, 5);
+      res.extend(ids(&current));
+      match current.next_cursor {
+        Some(cursor) => q.cursor = Some(Key::try_from_cursor(&cursor, q.sort).unwrap()),
+        None => break,
+      }
+    }
+    // Accent-insensitive name order: Ágyás, ásó, Kerti tömlő, Műtrágya, Virágföld
+    assert_eq!(res, vec![2, 4, 3, 5, 1]);
+    // Descending by ID
+    let res = page(products.iter(), &query(SortBy::Id, true, 3));
+    assert_eq!(ids(&res), vec![5, 4, 3]);
+    assert_eq!(res.next_cursor, Some("id:3".to_string()));
+  }
+
+  #[test]
+  fn test_page_filter() {
+    let products = products();
+    let mut q = query(SortBy::Id, false, DEFAULT_LIMIT);
+    q.filter.discontinued = Some(true);
+    let res = page(products.iter(), &q);
+    assert_eq!(ids(&res), vec![2, 4]);
+    assert_eq!(res.total, 2);
+    assert_eq!(res.next_cursor, None);
+    q.filter.created_by = Some(2);
+    assert_eq!(page(products.iter(), &q).total, 0);
+  }
+
+  #[test]
+  fn test_cursor() {
+    let key = Key::Name("kerti tomlo".into(), 3);
+    assert_eq!(
+      Key::try_from_cursor(&key.to_cursor(), SortBy::Name).unwrap(),
+      key
+    );
+    assert!(Key::try_from_cursor(&key.to_cursor(), SortBy::Id).is_err());
+    assert!(Key::try_from_cursor("name:zz:3", SortBy::Name).is_err());
+    assert!(Key::try_from_cursor("", SortBy::Id).is_err());
+  }
+}
@@ -1,9 +1,14 @@
-use gzlib::proto::{product::product_server::*, upl::upl_client::UplClient};
-use gzlib::proto::{product::*, upl::SetProductUnitRequest};
+use chrono::prelude::*;
+use gzlib::proto::upl::{upl_client::UplClient, SetProductUnitRequest};
 use packman::*;
 use prelude::*;
+use proto::{product_server::*, *};
 use quantity::{Quantity, Unit};
-use std::{env, path::PathBuf};
+use std::{
+  collections::{BTreeMap, HashMap},
+  env,
+  path::PathBuf,
+};
 use tokio::sync::{oneshot, Mutex};
 use tokio_stream::wrappers::ReceiverStream;
 use tonic::{
@@ -11,82 +16,492 @@ use tonic::{
   Request, Response, Status,
 };
 
+mod attribute;
+mod barcode;
+mod batch;
+mod brand;
+mod bundle;
+mod category;
 mod convert;
+mod divide;
+mod format;
+mod history;
+mod listing;
+mod packaging;
 mod prelude;
 mod product;
+mod proto;
 mod quantity;
+mod search;
+mod sku_image;
+mod tag;
+mod tax;
+mod validate;
 
 struct ProductService {
   products: Mutex<VecPack<product::Product>>,
   skus: Mutex<VecPack<product::Sku>>,
+  categories: Mutex<VecPack<category::Category>>,
+  brands: Mutex<VecPack<brand::Brand>>,
+  manufacturers: Mutex<VecPack<brand::Manufacturer>>,
+  tax_classes: Mutex<VecPack<tax::TaxClass>>,
+  images: sku_image::ImageStore,
+  // Barcode index key -> SKU ID
+  barcode_index: Mutex<HashMap<String, u32>>,
+  product_history: Mutex<VecPack<history::History>>,
+  sku_history: Mutex<VecPack<history::History>>,
   client_upl: Mutex<UplClient<Channel>>,
 }
 
 impl ProductService {
   /// Init new product service with the required DBs
+  #[allow(clippy::too_many_arguments)]
   fn init(
     product_db: VecPack<product::Product>,
     sku_db: VecPack<product::Sku>,
+    category_db: VecPack<category::Category>,
+    brand_db: VecPack<brand::Brand>,
+    manufacturer_db: VecPack<brand::Manufacturer>,
+    tax_class_db: VecPack<tax::TaxClass>,
+    image_store: sku_image::ImageStore,
+    product_history_db: VecPack<history::History>,
+    sku_history_db: VecPack<history::History>,
     client_upl: UplClient<Channel>,
   ) -> Self {
+    // Build barcode index
+    let mut barcode_index = HashMap::new();
+    sku_db.iter().for_each(|s| {
+      s.unpack().all_barcodes().iter().for_each(|b| {
+        barcode_index.insert(b.index_key(), s.unpack().sku);
+      })
+    });
     Self {
       products: Mutex::new(product_db),
       skus: Mutex::new(sku_db),
+      categories: Mutex::new(category_db),
+      brands: Mutex::new(brand_db),
+      manufacturers: Mutex::new(manufacturer_db),
+      tax_classes: Mutex::new(tax_class_db),
+      images: image_store,
+      barcode_index: Mutex::new(barcode_index),
+      product_history: Mutex::new(product_history_db),
+      sku_history: Mutex::new(sku_history_db),
       client_upl: Mutex::new(client_upl),
     }
   }
   /// Get next product id to use
+  /// IDs of deleted products are not reused
   async fn next_product_id(&self) -> u32 {
+    history::next_id(
+      self
+        .products
+        .lock()
+        .await
+        .iter()
+        .map(|p| p.unpack().product_id),
+      self.product_history.lock().await.iter().map(|h| h.unpack()),
+    )
+  }
+  /// Get next category id to use
+  async fn next_category_id(&self) -> u32 {
+    let mut latest_id: u32 = 0;
+    self.categories.lock().await.iter().for_each(|category| {
+      let id: u32 = *category.unpack().get_id();
+      if id > latest_id {
+        latest_id = id;
+      }
+    });
+    latest_id + 1
+  }
+  /// Get next brand id to use
+  async fn next_brand_id(&self) -> u32 {
     let mut latest_id: u32 = 0;
-    self.products.lock().await.iter().for_each(|product| {
-      let id: u32 = *product.unpack().get_id();
+    self.brands.lock().await.iter().for_each(|brand| {
+      let id: u32 = *brand.unpack().get_id();
       if id > latest_id {
         latest_id = id;
       }
     });
     latest_id + 1
   }
-  /// Get next SKU id to use
-  async fn next_sku(&self) -> u32 {
+  /// Get next manufacturer id to use
+  async fn next_manufacturer_id(&self) -> u32 {
+    let mut latest_id: u32 = 0;
+    self
+      .manufacturers
+      .lock()
+      .await
+      .iter()
+      .for_each(|manufacturer| {
+        let id: u32 = *manufacturer.unpack().get_id();
+        if id > latest_id {
+          latest_id = id;
+        }
+      });
+    latest_id + 1
+  }
+  /// Get next tax class id to use
+  async fn next_tax_class_id(&self) -> u32 {
     let mut latest_id: u32 = 0;
-    self.skus.lock().await.iter().for_each(|sku| {
-      let id: u32 = *sku.unpack().get_id();
+    self.tax_classes.lock().await.iter().for_each(|tax_class| {
+      let id: u32 = *tax_class.unpack().get_id();
       if id > latest_id {
         latest_id = id;
       }
     });
     latest_id + 1
   }
+  /// Update product through f and record the change in its history
+  /// f works on a copy, so the stored product changes only if f succeeds
+  async fn update_product_with<F>(
+    &self,
+    product_id: u32,
+    rpc: &str,
+    uid: u32,
+    f: F,
+  ) -> ServiceResult<product::Product>
+  where
+    F: FnOnce(&mut product::Product) -> ServiceResult<()>,
+  {
+    let (old, new) = {
+      let mut products = self.products.lock().await;
+      let product = products.find_id_mut(&product_id)?;
+      let old = product.unpack().clone();
+      let mut new = old.clone();
+      f(&mut new)?;
+      validate::check(validate::product(&new))?;
+      new.bump_version();
+      product.update(|p| *p = new.clone())?;
+      (old, new)
+    };
+    self
+      .log_product_history(product_id, rpc, uid, Some(&old), Some(&new))
+      .await?;
+    Ok(new)
+  }
+  /// Related product of a SKU
+  async fn sku_parent(&self, sku: u32) -> ServiceResult<product::Product> {
+    let product_id = self.skus.lock().await.find_id(&sku)?.unpack().product_id;
+    let res = self
+      .products
+      .lock()
+      .await
+      .find_id(&product_id)?
+      .unpack()
+      .clone();
+    Ok(res)
+  }
+  /// Update SKU through f and record the change in its history
+  /// f works on a copy, so the stored SKU changes only if f succeeds
+  async fn update_sku_with<F>(
+    &self,
+    sku: u32,
+    rpc: &str,
+    uid: u32,
+    f: F,
+  ) -> ServiceResult<product::Sku>
+  where
+    F: FnOnce(&mut product::Sku) -> ServiceResult<()>,
+  {
+    let parent = self.sku_parent(sku).await?;
+    let (old, new) = {
+      let mut skus = self.skus.lock().await;
+      let sku = skus.find_id_mut(&sku)?;
+      let old = sku.unpack().clone();
+      let mut new = old.clone();
+      f(&mut new)?;
+      validate::check(validate::sku(&new, Some(&parent)))?;
+      new.bump_version();
+      sku.update(|s| *s = new.clone())?;
+      (old, new)
+    };
+    self
+      .log_sku_history(sku, rpc, uid, Some(&old), Some(&new))
+      .await?;
+    Ok(new)
+  }
+  /// Update product through fp and all its SKUs through fs
+  /// and record the changes in their history
+  /// fs gets the updated product. Both work on copies under the same locks,
+  /// and every change is validated first, so either all of them are stored or none
+  async fn update_product_and_skus_with<F, G>(
+    &self,
+    product_id: u32,
+    rpc: &str,
+    uid: u32,
+    fp: F,
+    fs: G,
+  ) -> ServiceResult<product::Product>
+  where
+    F: FnOnce(&mut product::Product) -> ServiceResult<()>,
+    G: Fn(&product::Product, &mut product::Sku) -> ServiceResult<()>,
+  {
+    let (old, new, changes) = {
+      let mut products = self.products.lock().await;
+      let mut skus = self.skus.lock().await;
+      let product = products.find_id_mut(&product_id)?;
+      let old = product.unpack().clone();
+      let mut new = old.clone();
+      fp(&mut new)?;
+      validate::check(validate::product(&new))?;
+      new.bump_version();
+      let mut changes = Vec::new();
+      for sku in skus.iter().filter(|s| s.unpack().product_id == product_id) {
+        let old_sku = sku.unpack().clone();
+        let mut new_sku = old_sku.clone();
+        fs(&new, &mut new_sku)?;
+        validate::check(validate::sku(&new_sku, Some(&new)))?;
+        new_sku.bump_version();
+        changes.push((old_sku, new_sku));
+      }
+      product.update(|p| *p = new.clone())?;
+      for (old_sku, new_sku) in &changes {
+        skus
+          .find_id_mut(&old_sku.sku)?
+          .update(|s| *s = new_sku.clone())?;
+      }
+      (old, new, changes)
+    };
+    self
+      .log_product_history(product_id, rpc, uid, Some(&old), Some(&new))
+      .await?;
+    for (old_sku, new_sku) in changes {
+      self
+        .log_sku_history(old_sku.sku, rpc, uid, Some(&old_sku), Some(&new_sku))
+        .await?;
+    }
+    Ok(new)
+  }
+  /// Create a new SKU through f and add it to its parent product
+  /// f gets the parent product, the new SKU ID and the stored SKUs.
+  /// Everything is checked and stored under the products and skus locks,
+  /// so the parent cannot be deleted or archived in between
+  async fn create_sku_with<F>(
+    &self,
+    product_id: u32,
+    rpc: &str,
+    uid: u32,
+    f: F,
+  ) -> ServiceResult<product::Sku>
+  where
+    F: FnOnce(&product::Product, u32, &VecPack<product::Sku>) -> ServiceResult<product::Sku>,
+  {
+    let (sku, old_product, new_product) = {
+      let mut products = self.products.lock().await;
+      let mut skus = self.skus.lock().await;
+      // IDs of deleted SKUs are not reused
+      let next_sku_id = history::next_id(
+        skus.iter().map(|s| s.unpack().sku),
+        self.sku_history.lock().await.iter().map(|h| h.unpack()),
+      );
+      // Find product object as parent
+      let product = products.find_id_mut(&product_id).map_err(|_| {
+        ServiceError::bad_request("A SKU nem hozható létre, a megadott termék ID nem létezik!")
+      })?;
+      let old_product = product.unpack().clone();
+      // Check if product is archived
+      if old_product.archived {
+        return Err(ServiceError::bad_request(
+          "A SKU nem hozható létre, a megadott termék archivált!",
+        ));
+      }
+      let sku = f(&old_product, next_sku_id, &skus)?;
+      validate::check(validate::sku(&sku, Some(&old_product)))?;
+      let mut new_product = old_product.clone();
+      new_product.add_sku(sku.sku);
+      validate::check(validate::product(&new_product))?;
+      new_product.bump_version();
+      // Insert new SKU into storage
+      skus.insert(sku.clone())?;
+      // Remove the SKU if its parent cannot be updated
+      if let Err(e) = product.update(|p| *p = new_product.clone()) {
+        skus.remove_pack(&sku.sku)?;
+        return Err(e.into());
+      }
+      (sku, old_product, new_product)
+    };
+    // Record creation
+    self
+      .log_sku_history(sku.sku, rpc, uid, None, Some(&sku))
+      .await?;
+    self
+      .log_product_history(product_id, rpc, uid, Some(&old_product), Some(&new_product))
+      .await?;
+    Ok(sku)
+  }
+  /// Update the product unit in UPL
+  /// The product change is already stored, so a failure is only logged
+  async fn sync_upl_unit(&self, product: &product::Product) {
+    if let Err(e) = self
+      .client_upl
+      .lock()
+      .await
+      .set_product_unit(SetProductUnitRequest {
+        product_id: product.product_id,
+        unit: product.unit.to_string(),
+      })
+      .await
+    {
+      eprintln!(
+        "Error while updating UPL unit of product {}: {}",
+        product.product_id, e
+      );
+    }
+  }
+  /// Refresh component snapshots of every bundle SKU
+  /// and record the changed ones in their history
+  async fn sync_bundles(&self, rpc: &str, uid: u32) -> ServiceResult<()> {
+    let mut changes = Vec::new();
+    {
+      let mut skus = self.skus.lock().await;
+      let components = skus
+        .iter()
+        .map(|s| s.unpack().clone())
+        .collect::<Vec<product::Sku>>();
+      for sku in skus
+        .as_vec_mut()
+        .iter_mut()
+        .filter(|s| s.unpack().is_bundle())
+      {
+        let old = sku.unpack().clone();
+        let mut new = old.clone();
+        new.sync_bundle(|id| components.iter().find(|c| c.sku == id));
+        if new.bundle == old.bundle {
+          continue;
+        }
+        new.bump_version();
+        sku.update(|s| *s = new.clone())?;
+        changes.push((old, new));
+      }
+    }
+    for (old, new) in changes {
+      self
+        .log_sku_history(old.sku, rpc, uid, Some(&old), Some(&new))
+        .await?;
+    }
+    Ok(())
+  }
+  /// Migrate legacy Float SKU quantities to exact Decimal ones
+  /// and record the changed SKUs in their history
+  /// SKUs that cannot be migrated keep their Float quantity and are reported
+  async fn migrate_quantities(&self) -> ServiceResult<()> {
+    let mut changes = Vec::new();
+    {
+      let mut skus = self.skus.lock().await;
+      for sku in skus
+        .as_vec_mut()
+        .iter_mut()
+        .filter(|s| matches!(s.unpack().quantity, Quantity::Float(_)))
+      {
+        let old = sku.unpack().clone();
+        let quantity = match old.quantity.migrate() {
+          Ok(quantity) => quantity,
+          Err(e) => {
+            eprintln!("SKU {} quantity is not migrated: {}", old.sku, e);
+            continue;
+          }
+        };
+        let mut new = old.clone();
+        new.update(old.sub_name.clone(), quantity);
+        new.bump_version();
+        sku.update(|s| *s = new.clone())?;
+        changes.push((old, new));
+      }
+    }
+    for (old, new) in changes {
+      self
+        .log_sku_history(old.sku, "MigrateQuantity", 0, Some(&old), Some(&new))
+        .await?;
+    }
+    Ok(())
+  }
+  /// Record product change in history
+  async fn log_product_history(
+    &self,
+    product_id: u32,
+    rpc: &str,
+    uid: u32,
+    old: Option<&product::Product>,
+    new: Option<&product::Product>,
+  ) -> ServiceResult<()> {
+    log_history(
+      &self.product_history,
+      product_id,
+      rpc,
+      uid,
+      history::diff(old, new),
+    )
+    .await
+  }
+  /// Record SKU change in history
+  async fn log_sku_history(
+    &self,
+    sku: u32,
+    rpc: &str,
+    uid: u32,
+    old: Option<&product::Sku>,
+    new: Option<&product::Sku>,
+  ) -> ServiceResult<()> {
+    log_history(&self.sku_history, sku, rpc, uid, history::diff(old, new)).await
+  }
   // Create new product
   async fn create_product(&self, r: NewProduct) -> ServiceResult<ProductObj> {
+    // Check tax class
+    self.check_tax_class(r.tax_class_id).await?;
     // Get the next product id
     let next_product_id = self.next_product_id().await;
     // Create new product object
-    let new_product = product::Product::new(
+    let mut new_product = product::Product::new(
       next_product_id,
       r.name,
       r.description,
       Unit::try_from_str(&r.unit)?,
       r.created_by,
     );
+    new_product.set_tax_class(r.tax_class_id);
+    validate::check(validate::product(&new_product))?;
     // Store new product in storage
     self.products.lock().await.insert(new_product.clone())?;
+    // Record creation
+    self
+      .log_product_history(
+        new_product.product_id,
+        "CreateProduct",
+        new_product.created_by,
+        None,
+        Some(&new_product),
+      )
+      .await?;
     // Return new product as ProductObj
     Ok(new_product.into())
   }
   // Get all product
-  async fn get_product_all(&self) -> ServiceResult<Vec<u32>> {
+  async fn get_product_all(&self, r: GetProductAllRequest) -> ServiceResult<Vec<u32>> {
     // Create a product id vector from all the products available
     let res = self
       .products
       .lock()
       .await
       .iter()
+      .filter(|p| r.include_archived || !p.unpack().archived)
       .map(|p| *p.unpack().get_id())
       .collect::<Vec<u32>>();
     // Return ID vector
     Ok(res)
   }
+  // List products page by page
+  async fn list_products(&self, r: ListRequest) -> ServiceResult<ProductPage> {
+    let query = list_query(r)?;
+    let page = listing::page(
+      self.products.lock().await.iter().map(|p| p.unpack()),
+      &query,
+    );
+    Ok(ProductPage {
+      products: page.items.into_iter().map(|p| p.into()).collect(),
+      next_cursor: page.next_cursor.unwrap_or_default(),
+      total: page.total as u32,
+    })
+  }
   // Get product by ID
   async fn get_product(&self, r: GetProductRequest) -> ServiceResult<ProductObj> {
     // Try to find PID
@@ -115,111 +530,109 @@ impl ProductService {
     Ok(res)
   }
   // Tries to update product object
-  async fn update_product(&self, r: ProductObj) -> ServiceResult<ProductObj> {
+  async fn update_product(&self, r: ProductObj, uid: u32) -> ServiceResult<ProductObj> {
     // Define product_id to update
     let product_id = r.product_id;
-    // Find and update product
+    let unit = Unit::try_from_str(&r.unit)?;
+    // Find and update product and all related SKUs with product updates
     let res = self
-      .products
-      .lock()
-      .await
-      .find_id_mut(&r.product_id)?
-      .as_mut()
-      .unpack()
-      .update(r.name, r.description, Unit::try_from_str(&r.unit)?)
-      .clone();
-    // Update all related SKUs with product updates
-    self
-      .skus
-      .lock()
-      .await
-      .as_vec_mut()
-      .iter_mut()
-      .filter(|s| s.unpack().product_id == product_id)
-      .for_each(|s| {
-        s.as_mut().unpack().update_parent(&res);
-      });
+      .update_product_and_skus_with(
+        product_id,
+        "UpdateProduct",
+        uid,
+        |p| {
+          check_version(p.version, r.version)?;
+          p.update(r.name, r.description, unit);
+          Ok(())
+        },
+        |p, s| {
+          s.update_parent(p);
+          Ok(())
+        },
+      )
+      .await?;
 
     // Update UPLs product unit
-    self
-      .client_upl
-      .lock()
-      .await
-      .set_product_unit(SetProductUnitRequest {
-        product_id: res.product_id,
-        unit: res.unit.to_string(),
-      })
-      .await
-      .map_err(|e| ServiceError::bad_request(&e.to_string()))?;
+    self.sync_upl_unit(&res).await;
+
+    // Refresh bundles containing the changed SKUs
+    self.sync_bundles("UpdateProduct", uid).await?;
 
     // Return result as ProductObj
     Ok(res.into())
   }
   // Find products by query
   async fn find_product(&self, r: FindProductRequest) -> ServiceResult<Vec<u32>> {
-    // Filter products if their names contain the required query str
-    let res = self
-      .products
-      .lock()
-      .await
-      .iter()
-      .filter(|p| p.unpack().name.to_lowercase().contains(&r.query))
-      .map(|p| *p.unpack().get_id())
-      .collect::<Vec<u32>>();
+    let brand = optional_id(r.brand_id);
+    let manufacturer = optional_id(r.manufacturer_id);
+    let tags = tag::normalize_all(&r.tags)?;
+    // Products are matched exactly
+    let max_typos = 0;
+    // Rank products matching the query
+    // and the optional brand, manufacturer and tag filters
+    let products = self.products.lock().await;
+    let res = search::rank(
+      &r.query,
+      products
+        .iter()
+        .map(|p| p.unpack())
+        .filter(|p| brand.is_none() || p.brand == brand)
+        .filter(|p| manufacturer.is_none() || p.manufacturer == manufacturer)
+        .filter(|p| tag::matches(&p.tags, &tags, r.tags_any))
+        .filter(|p| r.include_archived || !p.archived)
+        .map(|p| (p.product_id, search::product_fields(p))),
+      max_typos,
+    );
     // Return result product id vector
-    Ok(res)
+    Ok(res.into_iter().map(|h| h.id).collect())
   }
   // Create new sku
   async fn create_sku(&self, r: NewSku) -> ServiceResult<SkuObj> {
-    // Query the next SKU ID
-    let next_sku_id = self.next_sku().await;
-    // Find product object as parent
-    let parent = self
-      .products
-      .lock()
-      .await
-      .find_id(&r.product_id)
-      .map_err(|_| {
-        ServiceError::bad_request("A SKU nem hozható létre, a megadott termék ID nem létezik!")
-      })?
-      .unpack()
-      .clone();
-    // Create new SKU object
-    let new_sku = product::Sku::new(
-      next_sku_id,
-      r.product_id,
-      &parent,
-      r.sub_name,
-      Quantity::try_from_str(&r.quantity)?,
-      r.created_by,
-    );
-    // Insert new SKU into storage
-    self.skus.lock().await.insert(new_sku.clone())?;
-    // Add SKU to its parent product
-    let _ = self
-      .products
-      .lock()
-      .await
-      .find_id_mut(&r.product_id)?
-      .as_mut()
-      .unpack()
-      .add_sku(new_sku.sku);
+    let new_sku = self
+      .create_sku_with(r.product_id, "CreateSku", r.created_by, |parent, id, _| {
+        // Create new SKU object
+        let mut new_sku = product::Sku::new(
+          id,
+          r.product_id,
+          parent,
+          r.sub_name,
+          Quantity::try_from_str_in(&r.quantity, &parent.unit)?,
+          r.created_by,
+        );
+        // Set its attributes by the product attribute schema
+        new_sku
+          .set_attributes(&parent.attributes, attribute_pairs(r.attributes))
+          .map_err(|e| ServiceError::bad_request(&e))?;
+        Ok(new_sku)
+      })
+      .await?;
     // Return new_sku as SkuObj
     Ok(new_sku.into())
   }
   // Get all SKU
-  async fn get_sku_all(&self) -> ServiceResult<Vec<u32>> {
+  async fn get_sku_all(&self, r: GetSkuAllRequest) -> ServiceResult<Vec<u32>> {
     // Collect all the IDs
     let res = self
       .skus
       .lock()
       .await
       .iter()
+      .filter(|s| r.include_archived || !s.unpack().archived)
       .map(|s| *s.unpack().get_id())
       .collect::<Vec<u32>>();
     // Return IDs as vector
     Ok(res)
   }
+  // List SKUs page by page
+  async fn list_skus(&self, r: ListRequest) -> ServiceResult<SkuPage> {
+    let query = list_query(r)?;
+    let page = listing::page(self.skus.lock().await.iter().map(|s| s.unpack()), &query);
+    Ok(SkuPage {
+      skus: page.items.into_iter().map(|s| s.into()).collect(),
+      next_cursor: page.next_cursor.unwrap_or_default(),
+      total: page.total as u32,
+    })
+  }
   // Get SKU by ID
   async fn get_sku(&self, r: GetSkuRequest) -> ServiceResult<SkuObj> {
     // Find SKU
@@ -242,186 +655,2078 @@ impl ProductService {
     Ok(res)
   }
   // Try to update SKU
-  async fn update_sku(&self, r: SkuObj) -> ServiceResult<SkuObj> {
+  async fn update_sku(&self, r: SkuObj, uid: u32) -> ServiceResult<SkuObj> {
     // Find and update SKU
+    // Quantity is parsed by the SKU unit
     let res = self
-      .skus
-      .lock()
-      .await
-      .find_id_mut(&r.sku)?
-      .as_mut()
-      .unpack()
-      .update(r.subname, Quantity::try_from_str(&r.quantity)?)
-      .clone();
+      .update_sku_with(r.sku, "UpdateSku", uid, |s| {
+        check_version(s.version, r.version)?;
+        let quantity = Quantity::try_from_str_in(&r.quantity, &s.unit)?;
+        s.update(r.subname, quantity);
+        Ok(())
+      })
+      .await?;
+    // Refresh bundles containing the changed SKUs
+    self.sync_bundles("UpdateSku", uid).await?;
+
     // Return SKU as SkuObj
     Ok(res.into())
   }
   // Try to update SKU divide
-  async fn update_sku_divide(&self, r: UpdateSkuDivideRequest) -> ServiceResult<SkuObj> {
+  async fn update_sku_divide(&self, r: UpdateSkuDivideRequest, uid: u32) -> ServiceResult<SkuObj> {
     // Find SKU and tries to update its divide
     let res = self
-      .skus
-      .lock()
-      .await
-      .find_id_mut(&r.sku)?
-      .as_mut()
-      .unpack()
-      .set_divide(r.can_divide)
-      .map_err(|e| ServiceError::bad_request(&e))?
-      .clone();
+      .update_sku_with(r.sku, "UpdateSkuDivide", uid, |s| {
+        s.set_divide(r.can_divide)
+          .map_err(|e| ServiceError::bad_request(&e))?;
+        Ok(())
+      })
+      .await?;
+    // Returns Sku as SkuObj
+    Ok(res.into())
+  }
+  // Try to update SKU split rule
+  async fn update_sku_divide_rule(
+    &self,
+    r: UpdateSkuDivideRuleRequest,
+    uid: u32,
+  ) -> ServiceResult<SkuObj> {
+    let rule = r
+      .divide_rule
+      .ok_or_else(|| ServiceError::bad_request("Hiányzó osztási szabály!"))?;
+    let fractions = rule
+      .fractions
+      .iter()
+      .map(|f| divide::Fraction::try_from_str(f))
+      .collect::<ServiceResult<Vec<divide::Fraction>>>()?;
+    // Find SKU and tries to update its split rule
+    let res = self
+      .update_sku_with(r.sku, "UpdateSkuDivideRule", uid, |s| {
+        let min_remaining = match rule.min_remaining.trim().is_empty() {
+          true => quantity::Decimal::default(),
+          false => Quantity::try_from_str_in(&rule.min_remaining, &s.unit)?.total(),
+        };
+        let rule = divide::DivideRule {
+          step: Quantity::try_from_str_in(&rule.step, &s.unit)?.total(),
+          min_remaining,
+          fractions,
+        };
+        s.set_divide_rule(rule)
+          .map_err(|e| ServiceError::bad_request(&e))?;
+        Ok(())
+      })
+      .await?;
     // Returns Sku as SkuObj
     Ok(res.into())
   }
+  // Check if amount can be split from an opened SKU
+  async fn check_sku_split(&self, r: CheckSkuSplitRequest) -> ServiceResult<SkuSplitObj> {
+    // Find SKU
+    let sku = self.skus.lock().await.find_id(&r.sku_id)?.unpack().clone();
+    let amount = Quantity::try_from_str_in(&r.amount, &sku.unit)?;
+    let (remaining, reason) = match sku.check_split(&amount) {
+      Ok(remaining) => (remaining.to_string(), String::default()),
+      Err(e) => (String::default(), e),
+    };
+    Ok(SkuSplitObj {
+      sku: sku.sku,
+      amount: amount.total().to_string(),
+      valid: reason.is_empty(),
+      remaining,
+      reason,
+    })
+  }
   // Find SKUs
   async fn find_sku(&self, r: FindSkuRequest) -> ServiceResult<Vec<u32>> {
-    // Filter SKUs if their display names contain the required query str
-    let res = self
-      .skus
+    let tags = tag::normalize_all(&r.tags)?;
+    let max_typos = search::check_typos(r.max_typos)?;
+    let descending = match r.sort.trim() {
+      "" => None,
+      "content" => Some(false),
+      "content_desc" => Some(true),
+      _ => {
+        return Err(ServiceError::bad_request(&format!(
+          "Hibás rendezés: {}. Lehetséges: content, content_desc",
+          r.sort
+        )))
+      }
+    };
+    // Rank SKUs matching the query and the optional tag filter
+    let descriptions = self.product_descriptions().await;
+    let skus = self.skus.lock().await;
+    let mut res = search::rank(
+      &r.query,
+      skus
+        .iter()
+        .map(|s| s.unpack())
+        .filter(|s| tag::matches(&s.tags, &tags, r.tags_any))
+        .filter(|s| r.include_archived || !s.archived)
+        .map(|s| {
+          (
+            s.sku,
+            search::sku_fields(s, description_of(&descriptions, s)),
+          )
+        }),
+      max_typos,
+    )
+    .into_iter()
+    .filter_map(|h| skus.find_id(&h.id).ok().map(|s| s.unpack()))
+    .collect::<Vec<&product::Sku>>();
+    // Sort by content if requested
+    if let Some(descending) = descending {
+      res.sort_by(|a, b| match descending {
+        true => b.quantity.cmp_content(&a.quantity),
+        false => a.quantity.cmp_content(&b.quantity),
+      });
+    }
+    // Return result SKU ids as vector
+    Ok(res.iter().map(|s| s.sku).collect())
+  }
+
+  // Search products by relevance
+  async fn search_product(&self, r: SearchRequest) -> ServiceResult<ProductHits> {
+    let max_typos = search::check_typos(r.max_typos)?;
+    let products = self.products.lock().await;
+    let hits = search::rank(
+      &r.query,
+      products
+        .iter()
+        .map(|p| p.unpack())
+        .filter(|p| r.include_archived || !p.archived)
+        .map(|p| (p.product_id, search::product_fields(p))),
+      max_typos,
+    );
+    let hits = hits
+      .into_iter()
+      .take(limit_of(r.limit))
+      .map(|h| {
+        Ok(ProductHitObj {
+          product_id: h.id,
+          name: products.find_id(&h.id)?.unpack().name.clone(),
+          score: h.score,
+        })
+      })
+      .collect::<ServiceResult<Vec<ProductHitObj>>>()?;
+    Ok(ProductHits { hits })
+  }
+  // Search SKUs by relevance
+  async fn search_sku(&self, r: SearchRequest) -> ServiceResult<SkuHits> {
+    let max_typos = search::check_typos(r.max_typos)?;
+    let descriptions = self.product_descriptions().await;
+    let skus = self.skus.lock().await;
+    let hits = search::rank(
+      &r.query,
+      skus
+        .iter()
+        .map(|s| s.unpack())
+        .filter(|s| r.include_archived || !s.archived)
+        .map(|s| {
+          (
+            s.sku,
+            search::sku_fields(s, description_of(&descriptions, s)),
+          )
+        }),
+      max_typos,
+    );
+    let hits = hits
+      .into_iter()
+      .take(limit_of(r.limit))
+      .map(|h| {
+        let sku = skus.find_id(&h.id)?.unpack();
+        Ok(SkuHitObj {
+          sku: sku.sku,
+          product_id: sku.product_id,
+          display_name: sku.display_name.clone(),
+          score: h.score,
+        })
+      })
+      .collect::<ServiceResult<Vec<SkuHitObj>>>()?;
+    Ok(SkuHits { hits })
+  }
+  /// Product descriptions by product ID
+  /// SKUs are searched by their product description too
+  async fn product_descriptions(&self) -> HashMap<u32, String> {
+    self
+      .products
       .lock()
       .await
       .iter()
-      .filter(|s| s.unpack().display_name.to_lowercase().contains(&r.query))
-      .map(|s| *s.unpack().get_id())
-      .collect::<Vec<u32>>();
-    // Return result SKU ids as vector
-    Ok(res)
+      .map(|p| (p.unpack().product_id, p.unpack().description.clone()))
+      .collect()
   }
 
   async fn update_product_discontinued(
     &self,
     r: UpdateProductDiscontinuedRequest,
+    uid: u32,
   ) -> ServiceResult<ProductObj> {
-    // Try set product
+    // Try set product and all related SKUs discontinued
     let res: ProductObj = self
-      .products
-      .lock()
-      .await
-      .find_id_mut(&r.product_id)?
-      .as_mut()
-      .unpack()
-      .set_discontinued(r.discontinued)
-      .clone()
+      .update_product_and_skus_with(
+        r.product_id,
+        "UpdateProductDiscontinued",
+        uid,
+        |p| {
+          p.set_discontinued(r.discontinued);
+          Ok(())
+        },
+        |_, i| {
+          i.set_discontinued(r.discontinued);
+          Ok(())
+        },
+      )
+      .await?
       .into();
 
-    // Set discontinued to all related SKUs
-    self
-      .skus
-      .lock()
-      .await
-      .as_vec_mut()
-      .iter_mut()
-      .filter(|i| i.unpack().product_id == r.product_id)
-      .for_each(|i| {
-        i.as_mut().unpack().set_discontinued(r.discontinued);
-      });
-
+    // Refresh bundles containing the changed SKUs
+    self.sync_bundles("UpdateProductDiscontinued", uid).await?;
     Ok(res)
   }
 
   async fn update_product_perishable(
     &self,
     r: UpdateProductPerishableRequest,
+    uid: u32,
   ) -> ServiceResult<ProductObj> {
-    // Try to update product
+    // Try to update product and its related SKUs
     let res: ProductObj = self
-      .products
-      .lock()
-      .await
-      .find_id_mut(&r.product_id)?
-      .as_mut()
-      .unpack()
-      .set_perishable(r.perishable)
-      .clone()
+      .update_product_and_skus_with(
+        r.product_id,
+        "UpdateProductPerishable",
+        uid,
+        |p| {
+          p.set_perishable(r.perishable);
+          Ok(())
+        },
+        |_, i| {
+          i.set_perishable(r.perishable);
+          Ok(())
+        },
+      )
+      .await?
       .into();
 
-    // Update related SKUs
-    self
-      .skus
-      .lock()
-      .await
-      .as_vec_mut()
-      .iter_mut()
-      .filter(|i| i.unpack().product_id == r.product_id)
-      .for_each(|i| {
-        i.as_mut().unpack().set_perishable(r.perishable);
-      });
-
     Ok(res)
   }
 
   async fn update_sku_discontinued(
     &self,
     r: UpdateSkuDiscontinuedRequest,
+    uid: u32,
   ) -> ServiceResult<SkuObj> {
-    // find product id
-    let product_id = self.skus.lock().await.find_id(&r.sku)?.unpack().product_id;
+    // Set sku
+    // SKUs of a discontinued product are checked by the validation
+    let res: SkuObj = self
+      .update_sku_with(r.sku, "UpdateSkuDiscontinued", uid, |s| {
+        s.set_discontinued(r.discontinued);
+        Ok(())
+      })
+      .await?
+      .into();
 
-    // Get product obj
-    let product = self
-      .products
-      .lock()
-      .await
-      .find_id(&product_id)?
-      .unpack()
-      .clone();
+    // Refresh bundles containing the changed SKUs
+    self.sync_bundles("UpdateSkuDiscontinued", uid).await?;
+    Ok(res)
+  }
 
-    // Check if we can update
-    if product.discontinued {
+  // Create new category
+  async fn create_category(&self, r: NewCategory) -> ServiceResult<CategoryObj> {
+    // Check name
+    let name = r.name.trim().to_string();
+    if name.is_empty() {
       return Err(ServiceError::bad_request(
-        "A termék kifutó termék. Nem lehet a SKU kivétel",
+        "A kategória neve nem lehet üres!",
       ));
     }
+    // Check parent if its not a root category
+    let parent = optional_id(r.parent_id);
+    if let Some(parent_id) = parent {
+      self
+        .categories
+        .lock()
+        .await
+        .find_id(&parent_id)
+        .map_err(|_| ServiceError::bad_request("A megadott szülő kategória nem létezik!"))?;
+    }
+    // Get the next category id
+    let next_category_id = self.next_category_id().await;
+    // Create new category object
+    let new_category = category::Category::new(next_category_id, parent, name, r.created_by);
+    // Store new category in storage
+    self.categories.lock().await.insert(new_category.clone())?;
+    // Return new category as CategoryObj
+    Ok(new_category.into())
+  }
 
-    // Set sku
-    let res: SkuObj = self
-      .skus
+  // Rename category
+  async fn rename_category(&self, r: RenameCategoryRequest) -> ServiceResult<CategoryObj> {
+    // Check name
+    let name = r.name.trim().to_string();
+    if name.is_empty() {
+      return Err(ServiceError::bad_request(
+        "A kategória neve nem lehet üres!",
+      ));
+    }
+    // Find and rename category
+    let res = self
+      .categories
       .lock()
       .await
-      .find_id_mut(&r.sku)?
+      .find_id_mut(&r.category_id)?
       .as_mut()
       .unpack()
-      .set_discontinued(r.discontinued)
-      .clone()
-      .into();
-
-    Ok(res)
+      .rename(name)
+      .clone();
+    // Return category as CategoryObj
+    Ok(res.into())
   }
-}
 
-#[tonic::async_trait]
-impl gzlib::proto::product::product_server::Product for ProductService {
-  async fn create_product(
-    &self,
-    request: Request<NewProduct>,
-  ) -> Result<Response<ProductObj>, Status> {
-    let res = self.create_product(request.into_inner()).await?;
-    Ok(Response::new(res))
+  // Move category under a new parent
+  async fn move_category(&self, r: MoveCategoryRequest) -> ServiceResult<CategoryObj> {
+    let mut categories = self.categories.lock().await;
+    // Check category
+    categories.find_id(&r.category_id)?;
+    // Check new parent if its not a move to root
+    let parent = optional_id(r.parent_id);
+    if let Some(parent_id) = parent {
+      categories
+        .find_id(&parent_id)
+        .map_err(|_| ServiceError::bad_request("A megadott szülő kategória nem létezik!"))?;
+      // Check cycle, parent cannot be the category itself
+      // or any of its descendants
+      if category::subtree_ids(categories.iter().map(|c| c.unpack()), r.category_id)
+        .contains(&parent_id)
+      {
+        return Err(ServiceError::bad_request(
+          "A kategória nem mozgatható saját magába vagy alkategóriájába!",
+        ));
+      }
+    }
+    // Set new parent
+    let res = categories
+      .find_id_mut(&r.category_id)?
+      .as_mut()
+      .unpack()
+      .set_parent(parent)
+      .clone();
+    // Return category as CategoryObj
+    Ok(res.into())
   }
 
-  async fn get_product_all(&self, _: Request<()>) -> Result<Response<ProductIds>, Status> {
-    let res = self.get_product_all().await?;
-    Ok(Response::new(ProductIds { product_ids: res }))
+  // Delete empty category
+  async fn delete_category(&self, r: DeleteCategoryRequest) -> ServiceResult<()> {
+    let mut categories = self.categories.lock().await;
+    // Check category
+    categories.find_id(&r.category_id)?;
+    // Check children
+    if category::has_child(categories.iter().map(|c| c.unpack()), r.category_id) {
+      return Err(ServiceError::bad_request(
+        "A kategória nem törölhető, mert van alkategóriája!",
+      ));
+    }
+    // Check related products
+    if self
+      .products
+      .lock()
+      .await
+      .iter()
+      .any(|p| p.unpack().categories.contains(&r.category_id))
+    {
+      return Err(ServiceError::bad_request(
+        "A kategória nem törölhető, mert termék tartozik hozzá!",
+      ));
+    }
+    // Remove category
+    categories.remove_pack(&r.category_id)?;
+    Ok(())
   }
 
-  async fn get_product(
-    &self,
-    request: Request<GetProductRequest>,
-  ) -> Result<Response<ProductObj>, Status> {
-    let res = self.get_product(request.into_inner()).await?;
-    Ok(Response::new(res))
+  // Get category subtree
+  async fn get_category_tree(&self, r: GetCategoryTreeRequest) -> ServiceResult<Vec<CategoryObj>> {
+    let categories = self.categories.lock().await;
+    let res = match optional_id(r.category_id) {
+      // Subtree starting with the requested category
+      Some(category_id) => {
+        categories.find_id(&category_id)?;
+        category::subtree_ids(categories.iter().map(|c| c.unpack()), category_id)
+          .iter()
+          .filter_map(|id| categories.find_id(id).ok())
+          .map(|c| c.unpack().clone().into())
+          .collect::<Vec<CategoryObj>>()
+      }
+      // Whole tree
+      None => categories
+        .iter()
+        .map(|c| c.unpack().clone().into())
+        .collect::<Vec<CategoryObj>>(),
+    };
+    Ok(res)
   }
 
-  type GetProductBulkStream = ReceiverStream<Result<ProductObj, Status>>;
-
-  async fn get_product_bulk(
-    &self,
+  // Get product IDs under a category, including its descendants
+  async fn get_category_products(&self, r: GetCategoryProductsRequest) -> ServiceResult<Vec<u32>> {
+    // Collect the category and all its descendants
+    let subtree = {
+      let categories = self.categories.lock().await;
+      categories.find_id(&r.category_id)?;
+      category::subtree_ids(categories.iter().map(|c| c.unpack()), r.category_id)
+    };
+    // Filter products that has at least one category in the subtree
+    let res = self
+      .products
+      .lock()
+      .await
+      .iter()
+      .filter(|p| p.unpack().categories.iter().any(|c| subtree.contains(c)))
+      .filter(|p| !p.unpack().archived)
+      .map(|p| *p.unpack().get_id())
+      .collect::<Vec<u32>>();
+    Ok(res)
+  }
+
+  // Set product categories
+  async fn set_product_categories(
+    &self,
+    r: SetProductCategoriesRequest,
+    uid: u32,
+  ) -> ServiceResult<ProductObj> {
+    // Remove duplicates but keep order
+    let mut category_ids: Vec<u32> = Vec::new();
+    r.category_ids.iter().for_each(|id| {
+      if !category_ids.contains(id) {
+        category_ids.push(*id);
+      }
+    });
+    // Check if all the categories exist
+    {
+      let categories = self.categories.lock().await;
+      for category_id in &category_ids {
+        categories.find_id(category_id).map_err(|_| {
+          ServiceError::bad_request(&format!(
+            "A megadott kategória nem létezik: {}",
+            category_id
+          ))
+        })?;
+      }
+    }
+    // Set product categories
+    let res = self
+      .update_product_with(r.product_id, "SetProductCategories", uid, |p| {
+        p.set_categories(category_ids);
+        Ok(())
+      })
+      .await?;
+    // Return product as ProductObj
+    Ok(res.into())
+  }
+
+  // Add new image to SKU
+  async fn add_sku_image(&self, r: NewSkuImage, uid: u32) -> ServiceResult<SkuImagesObj> {
+    // Check SKU
+    self.skus.lock().await.find_id(&r.sku)?;
+    // Process image
+    let format = sku_image::format_from_extension(&r.file_extension)?;
+    let (file_name, image_bytes) = (r.file_name, r.image_bytes);
+    let variants = tokio::task::spawn_blocking(move || sku_image::process(&image_bytes, format))
+      .await
+      .map_err(|e| ServiceError::internal_error(&e.to_string()))?
+      .map_err(|e| ServiceError::bad_request(&format!("{} ({})", e, file_name)))?;
+    // Store image files first, outside of the SKU lock
+    let sku = r.sku;
+    let taken = self
+      .skus
+      .lock()
+      .await
+      .find_id(&sku)?
+      .unpack()
+      .images
+      .clone();
+    let image_id = sku_image::new_image_id(&taken);
+    let (store, id) = (self.images.clone(), image_id.clone());
+    tokio::task::spawn_blocking(move || store.save(sku, &id, &variants))
+      .await
+      .map_err(|e| ServiceError::internal_error(&e.to_string()))??;
+    // Add its ID to the SKU, remove the files if it fails
+    let res = self
+      .update_sku_with(sku, "AddSkuImage", uid, |s| {
+        if s.images.contains(&image_id) {
+          return Err(ServiceError::conflict(
+            "A SKU képei időközben módosultak. Próbálja újra!",
+          ));
+        }
+        s.add_image(image_id.clone());
+        Ok(())
+      })
+      .await;
+    match res {
+      // Return SKU images
+      Ok(res) => Ok(res.into()),
+      Err(e) => {
+        self.images.remove(sku, &image_id)?;
+        Err(e)
+      }
+    }
+  }
+
+  // Set SKU primary image
+  async fn set_sku_primary_image(
+    &self,
+    r: SetSkuPrimaryImageRequest,
+    uid: u32,
+  ) -> ServiceResult<SkuImagesObj> {
+    let res = self
+      .update_sku_with(r.sku, "SetSkuPrimaryImage", uid, |s| {
+        s.set_primary_image(&r.image_id)
+          .map_err(|e| ServiceError::bad_request(&e))?;
+        Ok(())
+      })
+      .await?;
+    Ok(res.into())
+  }
+
+  // Reorder SKU images
+  async fn reorder_sku_images(
+    &self,
+    r: ReorderSkuImagesRequest,
+    uid: u32,
+  ) -> ServiceResult<SkuImagesObj> {
+    let res = self
+      .update_sku_with(r.sku, "ReorderSkuImages", uid, |s| {
+        s.reorder_images(r.image_ids)
+          .map_err(|e| ServiceError::bad_request(&e))?;
+        Ok(())
+      })
+      .await?;
+    Ok(res.into())
+  }
+
+  // Remove image from SKU and from the image store
+  async fn delete_sku_image(
+    &self,
+    r: DeleteSkuImageRequest,
+    uid: u32,
+  ) -> ServiceResult<SkuImagesObj> {
+    let res = self
+      .update_sku_with(r.sku, "DeleteSkuImage", uid, |s| {
+        s.remove_image(&r.image_id)
+          .map_err(|e| ServiceError::bad_request(&e))?;
+        Ok(())
+      })
+      .await?;
+    self.images.remove(r.sku, &r.image_id)?;
+    Ok(res.into())
+  }
+
+  // Get SKU images
+  async fn get_sku_images(&self, r: GetSkuRequest) -> ServiceResult<SkuImagesObj> {
+    let res = self.skus.lock().await.find_id(&r.sku_id)?.unpack().clone();
+    Ok(res.into())
+  }
+
+  // Create new brand
+  async fn create_brand(&self, r: NewBrand) -> ServiceResult<BrandObj> {
+    // Check name is not taken
+    let name = brand::check_name(
+      &r.name,
+      self
+        .brands
+        .lock()
+        .await
+        .iter()
+        .map(|b| b.unpack().name.as_str()),
+      "márka",
+    )?;
+    let next_brand_id = self.next_brand_id().await;
+    let new_brand = brand::Brand::new(next_brand_id, name, r.description, r.created_by);
+    self.brands.lock().await.insert(new_brand.clone())?;
+    Ok(new_brand.into())
+  }
+
+  // Get all brands
+  async fn get_brand_all(&self) -> ServiceResult<Vec<BrandObj>> {
+    let res = self
+      .brands
+      .lock()
+      .await
+      .iter()
+      .map(|b| b.unpack().clone().into())
+      .collect::<Vec<BrandObj>>();
+    Ok(res)
+  }
+
+  // Get brand by ID
+  async fn get_brand(&self, r: GetBrandRequest) -> ServiceResult<BrandObj> {
+    let res = self
+      .brands
+      .lock()
+      .await
+      .find_id(&r.brand_id)?
+      .unpack()
+      .clone();
+    Ok(res.into())
+  }
+
+  // Update brand
+  async fn update_brand(&self, r: BrandObj) -> ServiceResult<BrandObj> {
+    let mut brands = self.brands.lock().await;
+    // Check name is not taken by an other brand
+    let name = brand::check_name(
+      &r.name,
+      brands
+        .iter()
+        .filter(|b| b.unpack().brand_id != r.brand_id)
+        .map(|b| b.unpack().name.as_str()),
+      "márka",
+    )?;
+    let res = brands
+      .find_id_mut(&r.brand_id)?
+      .as_mut()
+      .unpack()
+      .update(name, r.description)
+      .clone();
+    Ok(res.into())
+  }
+
+  // Delete brand
+  async fn delete_brand(&self, r: GetBrandRequest) -> ServiceResult<()> {
+    let mut brands = self.brands.lock().await;
+    brands.find_id(&r.brand_id)?;
+    // Check related products
+    brand::check_unreferenced(
+      r.brand_id,
+      self.products.lock().await.iter().map(|p| p.unpack().brand),
+      "márka",
+    )?;
+    brands.remove_pack(&r.brand_id)?;
+    Ok(())
+  }
+
+  // Create new manufacturer
+  async fn create_manufacturer(&self, r: NewManufacturer) -> ServiceResult<ManufacturerObj> {
+    // Check name is not taken
+    let name = brand::check_name(
+      &r.name,
+      self
+        .manufacturers
+        .lock()
+        .await
+        .iter()
+        .map(|m| m.unpack().name.as_str()),
+      "gyártó",
+    )?;
+    let next_manufacturer_id = self.next_manufacturer_id().await;
+    let new_manufacturer =
+      brand::Manufacturer::new(next_manufacturer_id, name, r.description, r.created_by);
+    self
+      .manufacturers
+      .lock()
+      .await
+      .insert(new_manufacturer.clone())?;
+    Ok(new_manufacturer.into())
+  }
+
+  // Get all manufacturers
+  async fn get_manufacturer_all(&self) -> ServiceResult<Vec<ManufacturerObj>> {
+    let res = self
+      .manufacturers
+      .lock()
+      .await
+      .iter()
+      .map(|m| m.unpack().clone().into())
+      .collect::<Vec<ManufacturerObj>>();
+    Ok(res)
+  }
+
+  // Get manufacturer by ID
+  async fn get_manufacturer(&self, r: GetManufacturerRequest) -> ServiceResult<ManufacturerObj> {
+    let res = self
+      .manufacturers
+      .lock()
+      .await
+      .find_id(&r.manufacturer_id)?
+      .unpack()
+      .clone();
+    Ok(res.into())
+  }
+
+  // Update manufacturer
+  async fn update_manufacturer(&self, r: ManufacturerObj) -> ServiceResult<ManufacturerObj> {
+    let mut manufacturers = self.manufacturers.lock().await;
+    // Check name is not taken by an other manufacturer
+    let name = brand::check_name(
+      &r.name,
+      manufacturers
+        .iter()
+        .filter(|m| m.unpack().manufacturer_id != r.manufacturer_id)
+        .map(|m| m.unpack().name.as_str()),
+      "gyártó",
+    )?;
+    let res = manufacturers
+      .find_id_mut(&r.manufacturer_id)?
+      .as_mut()
+      .unpack()
+      .update(name, r.description)
+      .clone();
+    Ok(res.into())
+  }
+
+  // Delete manufacturer
+  async fn delete_manufacturer(&self, r: GetManufacturerRequest) -> ServiceResult<()> {
+    let mut manufacturers = self.manufacturers.lock().await;
+    manufacturers.find_id(&r.manufacturer_id)?;
+    // Check related products
+    brand::check_unreferenced(
+      r.manufacturer_id,
+      self
+        .products
+        .lock()
+        .await
+        .iter()
+        .map(|p| p.unpack().manufacturer),
+      "gyártó",
+    )?;
+    manufacturers.remove_pack(&r.manufacturer_id)?;
+    Ok(())
+  }
+
+  // Add barcode to SKU
+  async fn add_sku_barcode(&self, r: AddSkuBarcodeRequest, uid: u32) -> ServiceResult<SkuObj> {
+    let kind = match r.kind.trim().is_empty() {
+      true => None,
+      false => Some(barcode::BarcodeKind::try_from_str(&r.kind)?),
+    };
+    let barcode = barcode::Barcode::try_new(&r.barcode, kind)?;
+    // Hold the index lock, so no one can take the barcode meanwhile
+    let mut barcode_index = self.barcode_index.lock().await;
+    // Check global uniqueness
+    if let Some(sku) = barcode_index.get(&barcode.index_key()) {
+      return Err(ServiceError::already_exist(&format!(
+        "A vonalkód már egy másik SKU-hoz tartozik: {}",
+        sku
+      )));
+    }
+    // Add barcode to SKU
+    let key = barcode.index_key();
+    let res = self
+      .update_sku_with(r.sku, "AddSkuBarcode", uid, |s| {
+        s.add_barcode(barcode)
+          .map_err(|e| ServiceError::bad_request(&e))?;
+        Ok(())
+      })
+      .await?;
+    barcode_index.insert(key, r.sku);
+    Ok(res.into())
+  }
+
+  // Remove barcode from SKU
+  async fn remove_sku_barcode(
+    &self,
+    r: RemoveSkuBarcodeRequest,
+    uid: u32,
+  ) -> ServiceResult<SkuObj> {
+    let mut barcode_index = self.barcode_index.lock().await;
+    let mut removed = None;
+    let res = self
+      .update_sku_with(r.sku, "RemoveSkuBarcode", uid, |s| {
+        removed = Some(
+          s.remove_barcode(&r.barcode)
+            .map_err(|e| ServiceError::bad_request(&e))?,
+        );
+        Ok(())
+      })
+      .await?;
+    if let Some(removed) = removed {
+      barcode_index.remove(&removed.index_key());
+    }
+    Ok(res.into())
+  }
+
+  // Get SKU by barcode
+  async fn get_sku_by_barcode(&self, r: GetSkuByBarcodeRequest) -> ServiceResult<SkuObj> {
+    let sku_id = {
+      let barcode_index = self.barcode_index.lock().await;
+      barcode::lookup_keys(&r.barcode)
+        .iter()
+        .find_map(|key| barcode_index.get(key).cloned())
+        .ok_or_else(|| ServiceError::not_found("A vonalkódhoz nem tartozik SKU!"))?
+    };
+    let res = self.skus.lock().await.find_id(&sku_id)?.unpack().clone();
+    Ok(res.into())
+  }
+
+  // Archive product and all its SKUs
+  async fn archive_product(&self, r: GetProductRequest, uid: u32) -> ServiceResult<ProductObj> {
+    self
+      .set_product_archived(r.product_id, true, "ArchiveProduct", uid)
+      .await
+  }
+
+  // Restore archived product and the SKUs archived with it
+  async fn restore_product(&self, r: GetProductRequest, uid: u32) -> ServiceResult<ProductObj> {
+    self
+      .set_product_archived(r.product_id, false, "RestoreProduct", uid)
+      .await
+  }
+
+  // Set product and its related SKUs archived
+  async fn set_product_archived(
+    &self,
+    product_id: u32,
+    archived: bool,
+    rpc: &str,
+    uid: u32,
+  ) -> ServiceResult<ProductObj> {
+    // Set archived to the product and all related SKUs
+    // SKUs archived on their own are left untouched
+    let res: ProductObj = self
+      .update_product_and_skus_with(
+        product_id,
+        rpc,
+        uid,
+        |p| {
+          p.set_archived(archived);
+          Ok(())
+        },
+        |_, i| {
+          match archived {
+            true => i.archive_with_product(),
+            false => i.restore_with_product(),
+          };
+          Ok(())
+        },
+      )
+      .await?
+      .into();
+
+    Ok(res)
+  }
+
+  // Delete product
+  // Only products without SKUs can be deleted
+  async fn delete_product(&self, r: GetProductRequest, uid: u32) -> ServiceResult<()> {
+    let product = {
+      let mut products = self.products.lock().await;
+      // Check related SKUs
+      if !products.find_id(&r.product_id)?.unpack().skus.is_empty() {
+        return Err(ServiceError::bad_request(
+          "A termék nem törölhető, mert SKU tartozik hozzá!",
+        ));
+      }
+      products.remove_pack(&r.product_id)?
+    };
+    // Record deletion
+    self
+      .log_product_history(
+        product.product_id,
+        "DeleteProduct",
+        uid,
+        Some(&product),
+        None,
+      )
+      .await
+  }
+
+  // Archive SKU
+  async fn archive_sku(&self, r: GetSkuRequest, uid: u32) -> ServiceResult<SkuObj> {
+    let res = self
+      .update_sku_with(r.sku_id, "ArchiveSku", uid, |s| {
+        s.set_archived(true);
+        Ok(())
+      })
+      .await?;
+    Ok(res.into())
+  }
+
+  // Restore archived SKU
+  async fn restore_sku(&self, r: GetSkuRequest, uid: u32) -> ServiceResult<SkuObj> {
+    // find product id
+    let product_id = self
+      .skus
+      .lock()
+      .await
+      .find_id(&r.sku_id)?
+      .unpack()
+      .product_id;
+
+    // Check if we can restore
+    if self
+      .products
+      .lock()
+      .await
+      .find_id(&product_id)?
+      .unpack()
+      .archived
+    {
+      return Err(ServiceError::bad_request(
+        "A termék archivált. Előbb a terméket kell visszaállítani!",
+      ));
+    }
+
+    let res = self
+      .update_sku_with(r.sku_id, "RestoreSku", uid, |s| {
+        s.set_archived(false);
+        Ok(())
+      })
+      .await?;
+    Ok(res.into())
+  }
+
+  // Delete SKU
+  // Removes it from its parent product, the barcode index
+  // and the image store
+  async fn delete_sku(&self, r: GetSkuRequest, uid: u32) -> ServiceResult<()> {
+    // Remove SKU and its ID from the parent product under the same locks,
+    // so the product never refers to a deleted SKU
+    let (sku, old_product, new_product) = {
+      let mut products = self.products.lock().await;
+      let mut skus = self.skus.lock().await;
+      // Check if SKU is a bundle component
+      if let Some(bundle) = skus
+        .iter()
+        .find(|s| s.unpack().bundle.iter().any(|i| i.sku == r.sku_id))
+      {
+        return Err(ServiceError::bad_request(&format!(
+          "A SKU nem törölhető, mert egy csomag összetevője: {}",
+          bundle.unpack().sku
+        )));
+      }
+      let product_id = skus.find_id(&r.sku_id)?.unpack().product_id;
+      let product = products.find_id_mut(&product_id)?;
+      let old_product = product.unpack().clone();
+      let mut new_product = old_product.clone();
+      new_product.remove_sku(r.sku_id);
+      new_product.bump_version();
+      product.update(|p| *p = new_product.clone())?;
+      // Put back the product if the SKU cannot be removed
+      let sku = match skus.remove_pack(&r.sku_id) {
+        Ok(sku) => sku,
+        Err(e) => {
+          products
+            .find_id_mut(&product_id)?
+            .update(|p| *p = old_product.clone())?;
+          return Err(e.into());
+        }
+      };
+      (sku, old_product, new_product)
+    };
+    // Record deletion
+    self
+      .log_sku_history(sku.sku, "DeleteSku", uid, Some(&sku), None)
+      .await?;
+    self
+      .log_product_history(
+        sku.product_id,
+        "DeleteSku",
+        uid,
+        Some(&old_product),
+        Some(&new_product),
+      )
+      .await?;
+    // Remove its barcodes from the index
+    let mut barcode_index = self.barcode_index.lock().await;
+    sku.all_barcodes().iter().for_each(|b| {
+      barcode_index.remove(&b.index_key());
+    });
+    // Remove its images
+    for image_id in &sku.images {
+      self.images.remove(sku.sku, image_id)?;
+    }
+    Ok(())
+  }
+
+  // Set product brand and manufacturer
+  async fn set_product_brand(
+    &self,
+    r: SetProductBrandRequest,
+    uid: u32,
+  ) -> ServiceResult<ProductObj> {
+    let brand = optional_id(r.brand_id);
+    let manufacturer = optional_id(r.manufacturer_id);
+    // Check brand
+    if let Some(brand_id) = brand {
+      self
+        .brands
+        .lock()
+        .await
+        .find_id(&brand_id)
+        .map_err(|_| ServiceError::bad_request("A megadott márka nem létezik!"))?;
+    }
+    // Check manufacturer
+    if let Some(manufacturer_id) = manufacturer {
+      self
+        .manufacturers
+        .lock()
+        .await
+        .find_id(&manufacturer_id)
+        .map_err(|_| ServiceError::bad_request("A megadott gyártó nem létezik!"))?;
+    }
+    // Set product brand and manufacturer
+    let res = self
+      .update_product_with(r.product_id, "SetProductBrand", uid, |p| {
+        p.set_brand(brand, manufacturer);
+        Ok(())
+      })
+      .await?;
+    Ok(res.into())
+  }
+
+  // Set product SKU attribute schema
+  async fn set_product_attribute_schema(
+    &self,
+    r: SetProductAttributeSchemaRequest,
+    uid: u32,
+  ) -> ServiceResult<ProductObj> {
+    let product_id = r.product_id;
+    // Build schema
+    let mut schema = Vec::new();
+    for a in r.attributes {
+      let kind = attribute::AttributeKind::try_new(&a.kind, a.options)
+        .map_err(|e| ServiceError::bad_request(&e))?;
+      schema.push(
+        attribute::AttributeDef::try_new(&a.name, kind, a.required, a.show_in_name)
+          .map_err(|e| ServiceError::bad_request(&e))?,
+      );
+    }
+    attribute::validate_schema(&schema).map_err(|e| ServiceError::bad_request(&e))?;
+    // Set product schema and sync related SKUs,
+    // fails if any of the SKUs does not fit the new schema
+    let res = self
+      .update_product_and_skus_with(
+        product_id,
+        "SetProductAttributeSchema",
+        uid,
+        |p| {
+          p.set_attribute_schema(schema)
+            .map_err(|e| ServiceError::bad_request(&e))?;
+          Ok(())
+        },
+        |p, s| {
+          let sku = s.sku;
+          s.sync_attributes(&p.attributes)
+            .map_err(|e| ServiceError::bad_request(&format!("SKU {}: {}", sku, e)))?;
+          Ok(())
+        },
+      )
+      .await?;
+
+    // Refresh bundles containing the changed SKUs
+    self.sync_bundles("SetProductAttributeSchema", uid).await?;
+    Ok(res.into())
+  }
+
+  // Set SKU attribute values
+  async fn set_sku_attributes(
+    &self,
+    r: SetSkuAttributesRequest,
+    uid: u32,
+  ) -> ServiceResult<SkuObj> {
+    let sku = r.sku;
+    // Get parent attribute schema
+    let product_id = self.skus.lock().await.find_id(&sku)?.unpack().product_id;
+    let schema = self
+      .products
+      .lock()
+      .await
+      .find_id(&product_id)?
+      .unpack()
+      .attributes
+      .clone();
+    // Set SKU attributes
+    let values = attribute_pairs(r.attributes);
+    let res = self
+      .update_sku_with(sku, "SetSkuAttributes", uid, |s| {
+        s.set_attributes(&schema, values)
+          .map_err(|e| ServiceError::bad_request(&e))?;
+        Ok(())
+      })
+      .await?;
+
+    // Refresh bundles containing the changed SKUs
+    self.sync_bundles("SetSkuAttributes", uid).await?;
+    Ok(res.into())
+  }
+
+  // Find SKUs having all the given attribute values
+  async fn find_sku_by_attributes(&self, r: FindSkuByAttributesRequest) -> ServiceResult<Vec<u32>> {
+    let product_id = optional_id(r.product_id);
+    let include_archived = r.include_archived;
+    let values = attribute_pairs(r.attributes);
+    let res = self
+      .skus
+      .lock()
+      .await
+      .iter()
+      .filter(|s| match product_id {
+        Some(id) => s.unpack().product_id == id,
+        None => true,
+      })
+      .filter(|s| include_archived || !s.unpack().archived)
+      .filter(|s| s.unpack().has_attributes(&values))
+      .map(|s| s.unpack().sku)
+      .collect::<Vec<u32>>();
+    Ok(res)
+  }
+
+  // Add or remove tags of products
+  // Every product must exist and stay valid, otherwise nothing is changed
+  async fn update_product_tags(
+    &self,
+    r: ProductTagsRequest,
+    add: bool,
+    uid: u32,
+  ) -> ServiceResult<Vec<u32>> {
+    let tags = tag::normalize_all(&r.tags)?;
+    let rpc = match add {
+      true => "AddProductTags",
+      false => "RemoveProductTags",
+    };
+    let mut changes: Vec<(product::Product, product::Product)> = Vec::new();
+    {
+      let mut products = self.products.lock().await;
+      // Check and validate every product first,
+      // so either all of them are updated or none
+      for product_id in &r.product_ids {
+        if changes.iter().any(|(old, _)| old.product_id == *product_id) {
+          continue;
+        }
+        let old = products.find_id(product_id)?.unpack().clone();
+        let mut new = old.clone();
+        match add {
+          true => new.add_tags(&tags),
+          false => new.remove_tags(&tags),
+        };
+        validate::check(validate::product(&new))?;
+        new.bump_version();
+        changes.push((old, new));
+      }
+      for (_, new) in &changes {
+        products
+          .find_id_mut(&new.product_id)?
+          .update(|p| *p = new.clone())?;
+      }
+    }
+    for (old, new) in changes {
+      self
+        .log_product_history(old.product_id, rpc, uid, Some(&old), Some(&new))
+        .await?;
+    }
+    Ok(r.product_ids)
+  }
+
+  // Add or remove tags of SKUs
+  // Every SKU must exist and stay valid, otherwise nothing is changed
+  async fn update_sku_tags(
+    &self,
+    r: SkuTagsRequest,
+    add: bool,
+    uid: u32,
+  ) -> ServiceResult<Vec<u32>> {
+    let tags = tag::normalize_all(&r.tags)?;
+    let rpc = match add {
+      true => "AddSkuTags",
+      false => "RemoveSkuTags",
+    };
+    let mut changes: Vec<(product::Sku, product::Sku)> = Vec::new();
+    {
+      let products = self.products.lock().await;
+      let mut skus = self.skus.lock().await;
+      // Check and validate every SKU first,
+      // so either all of them are updated or none
+      for sku in &r.skus {
+        if changes.iter().any(|(old, _)| old.sku == *sku) {
+          continue;
+        }
+        let old = skus.find_id(sku)?.unpack().clone();
+        let parent = products.find_id(&old.product_id)?.unpack();
+        let mut new = old.clone();
+        match add {
+          true => new.add_tags(&tags),
+          false => new.remove_tags(&tags),
+        };
+        validate::check(validate::sku(&new, Some(parent)))?;
+        new.bump_version();
+        changes.push((old, new));
+      }
+      for (_, new) in &changes {
+        skus.find_id_mut(&new.sku)?.update(|s| *s = new.clone())?;
+      }
+    }
+    for (old, new) in changes {
+      self
+        .log_sku_history(old.sku, rpc, uid, Some(&old), Some(&new))
+        .await?;
+    }
+    Ok(r.skus)
+  }
+
+  // Get all the used tags with their usage counts
+  async fn get_tag_all(&self) -> ServiceResult<Vec<TagObj>> {
+    let mut counts: BTreeMap<String, (u32, u32)> = BTreeMap::new();
+    self.products.lock().await.iter().for_each(|p| {
+      p.unpack().tags.iter().for_each(|t| {
+        counts.entry(t.clone()).or_default().0 += 1;
+      })
+    });
+    self.skus.lock().await.iter().for_each(|s| {
+      s.unpack().tags.iter().for_each(|t| {
+        counts.entry(t.clone()).or_default().1 += 1;
+      })
+    });
+    let res = counts
+      .into_iter()
+      .map(|(tag, (product_count, sku_count))| TagObj {
+        tag,
+        product_count,
+        sku_count,
+      })
+      .collect();
+    Ok(res)
+  }
+
+  // Create new bundle SKU from existing component SKUs
+  async fn create_bundle_sku(&self, r: NewBundleSku) -> ServiceResult<SkuObj> {
+    let components = r
+      .components
+      .iter()
+      .map(|c| (c.sku, c.quantity))
+      .collect::<Vec<(u32, u32)>>();
+    let new_sku = self
+      .create_sku_with(
+        r.product_id,
+        "CreateBundleSku",
+        r.created_by,
+        |parent, id, skus| {
+          // Bundles are sold by piece
+          if parent.unit != Unit::Piece {
+            return Err(ServiceError::bad_request(
+              "Csomag csak darab egységű termékhez hozható létre!",
+            ));
+          }
+          // Validate components
+          let items =
+            bundle::validate(&components, |id| skus.find_id(&id).ok().map(|s| s.unpack()))
+              .map_err(|e| ServiceError::bad_request(&e))?;
+          // Create new bundle SKU object
+          let mut new_sku = product::Sku::new(
+            id,
+            r.product_id,
+            parent,
+            r.sub_name,
+            Quantity::Simple(1),
+            r.created_by,
+          );
+          new_sku
+            .set_attributes(&parent.attributes, attribute_pairs(r.attributes))
+            .map_err(|e| ServiceError::bad_request(&e))?;
+          new_sku.set_bundle(items);
+          Ok(new_sku)
+        },
+      )
+      .await?;
+    Ok(new_sku.into())
+  }
+
+  // Create and update products and SKUs in one step
+  // Every item is validated first, nothing is stored
+  // if any of them is invalid
+  async fn batch_catalog(
+    &self,
+    r: BatchCatalogRequest,
+    uid: u32,
+  ) -> ServiceResult<BatchCatalogObj> {
+    let tax_classes = self
+      .tax_classes
+      .lock()
+      .await
+      .iter()
+      .map(|t| *t.unpack().get_id())
+      .collect::<Vec<u32>>();
+    // Hold both locks, so no one can change the catalog
+    // between validation and storing
+    let plan = {
+      let mut products = self.products.lock().await;
+      let mut skus = self.skus.lock().await;
+      let next_ids = (
+        history::next_id(
+          products.iter().map(|p| p.unpack().product_id),
+          self.product_history.lock().await.iter().map(|h| h.unpack()),
+        ),
+        history::next_id(
+          skus.iter().map(|s| s.unpack().sku),
+          self.sku_history.lock().await.iter().map(|h| h.unpack()),
+        ),
+      );
+      let plan = batch::plan(
+        products.iter().map(|p| p.unpack()),
+        skus.iter().map(|s| s.unpack()),
+        next_ids,
+        &tax_classes,
+        r,
+      )?;
+      store_batch(&mut products, &mut skus, &plan)?;
+      plan
+    };
+    // Record changes
+    for (old, new) in &plan.products {
+      self
+        .log_product_history(new.product_id, "BatchCatalog", uid, old.as_ref(), Some(new))
+        .await?;
+    }
+    for (old, new) in &plan.skus {
+      self
+        .log_sku_history(new.sku, "BatchCatalog", uid, old.as_ref(), Some(new))
+        .await?;
+    }
+    // Update UPLs product unit
+    for product_id in &plan.product_updates {
+      if let Some((_, p)) = plan
+        .products
+        .iter()
+        .find(|(_, p)| p.product_id == *product_id)
+      {
+        self.sync_upl_unit(p).await;
+      }
+    }
+    // Refresh bundles containing the changed SKUs
+    self.sync_bundles("BatchCatalog", uid).await?;
+    let product_obj = |id: &u32| -> ProductObj {
+      plan
+        .products
+        .iter()
+        .find(|(_, p)| p.product_id == *id)
+        .map(|(_, p)| p.clone().into())
+        .unwrap_or_default()
+    };
+    let sku_obj = |id: &u32| -> SkuObj {
+      plan
+        .skus
+        .iter()
+        .find(|(_, s)| s.sku == *id)
+        .map(|(_, s)| s.clone().into())
+        .unwrap_or_default()
+    };
+    Ok(BatchCatalogObj {
+      new_products: plan.new_products.iter().map(product_obj).collect(),
+      product_updates: plan.product_updates.iter().map(product_obj).collect(),
+      new_skus: plan.new_skus.iter().map(sku_obj).collect(),
+      sku_updates: plan.sku_updates.iter().map(sku_obj).collect(),
+    })
+  }
+
+  // Get SKU content in the requested unit
+  async fn get_sku_content(&self, r: GetSkuContentRequest) -> ServiceResult<SkuContentObj> {
+    let target = convert::MeasureUnit::try_from_str(&r.unit)?;
+    let profile = format::FormatProfile::try_from_locale(&r.locale)?;
+    // Find SKU
+    let sku = self.skus.lock().await.find_id(&r.sku_id)?.unpack().clone();
+    // Convert its quantity
+    let res = convert::express_in(&sku.quantity, &sku.unit, target)?;
+    Ok(SkuContentObj {
+      sku: sku.sku,
+      unit: res.unit.to_string(),
+      quantity: res.quantity(),
+      total: res.total()?.to_string(),
+      display: res.display_with(&profile),
+    })
+  }
+
+  // Get SKU unit price by its gross price
+  async fn get_sku_unit_price(&self, r: GetSkuUnitPriceRequest) -> ServiceResult<SkuUnitPriceObj> {
+    let profile = format::FormatProfile::try_from_locale(&r.locale)?;
+    // Find SKU
+    let sku = self.skus.lock().await.find_id(&r.sku_id)?.unpack().clone();
+    // Compute its unit price
+    let content = convert::comparison_quantity(&sku.quantity, &sku.unit)?;
+    let unit_price = convert::unit_price(r.gross_price, &sku.quantity, &sku.unit)?;
+    Ok(SkuUnitPriceObj {
+      sku: sku.sku,
+      gross_price: r.gross_price,
+      unit_price,
+      comparison_quantity: content.amount.to_string(),
+      comparison_unit: content.unit.to_string(),
+      display: convert::display_unit_price_with(unit_price, &sku.unit, &profile),
+    })
+  }
+
+  // Set SKU packaging levels
+  // Level barcodes are registered in the barcode index
+  async fn set_sku_packaging(&self, r: SetSkuPackagingRequest, uid: u32) -> ServiceResult<SkuObj> {
+    let levels = r
+      .levels
+      .into_iter()
+      .map(|l| {
+        let barcode = match l.barcode.trim().is_empty() {
+          true => None,
+          false => {
+            let kind = match l.barcode_kind.trim().is_empty() {
+              true => None,
+              false => Some(barcode::BarcodeKind::try_from_str(&l.barcode_kind)?),
+            };
+            Some(barcode::Barcode::try_new(&l.barcode, kind)?)
+          }
+        };
+        Ok(packaging::PackagingLevel {
+          name: l.name.trim().to_string(),
+          count: l.count,
+          barcode,
+        })
+      })
+      .collect::<ServiceResult<Vec<packaging::PackagingLevel>>>()?;
+    // Hold the index lock, so no one can take the barcodes meanwhile
+    let mut barcode_index = self.barcode_index.lock().await;
+    // Check global uniqueness
+    for barcode in levels.iter().filter_map(|l| l.barcode.as_ref()) {
+      if let Some(sku) = barcode_index.get(&barcode.index_key()) {
+        if *sku != r.sku_id {
+          return Err(ServiceError::already_exist(&format!(
+            "A vonalkód már egy másik SKU-hoz tartozik: {}",
+            sku
+          )));
+        }
+      }
+    }
+    let mut old_levels = Vec::new();
+    let res = self
+      .update_sku_with(r.sku_id, "SetSkuPackaging", uid, |s| {
+        old_levels = s.packaging.clone();
+        s.set_packaging(levels)
+          .map_err(|e| ServiceError::bad_request(&e))?;
+        Ok(())
+      })
+      .await?;
+    // Update barcode index
+    old_levels
+      .iter()
+      .filter_map(|l| l.barcode.as_ref())
+      .for_each(|b| {
+        barcode_index.remove(&b.index_key());
+      });
+    res
+      .packaging
+      .iter()
+      .filter_map(|l| l.barcode.as_ref())
+      .for_each(|b| {
+        barcode_index.insert(b.index_key(), res.sku);
+      });
+    Ok(res.into())
+  }
+
+  // Parse packaging expression
+  async fn parse_packaging(&self, r: ParsePackagingRequest) -> ServiceResult<ParsedPackagingObj> {
+    let unit = Unit::try_from_str(&r.unit)?;
+    let profile = format::FormatProfile::try_from_locale(&r.locale)?;
+    let (counts, quantity) = packaging::parse(&r.expression, &unit)?;
+    Ok(ParsedPackagingObj {
+      quantity: quantity.to_string(),
+      counts: counts.iter().rev().cloned().collect(),
+      total: packaging::total(&counts, &quantity)
+        .map_err(|e| ServiceError::bad_request(&e))?
+        .to_string(),
+      display: packaging::display_with(&counts, &quantity, &unit, &profile),
+    })
+  }
+
+  // Get SKU with display strings in the requested locale
+  async fn get_sku_localized(&self, r: GetSkuLocalizedRequest) -> ServiceResult<SkuObj> {
+    let profile = format::FormatProfile::try_from_locale(&r.locale)?;
+    // Find SKU
+    let res = self.skus.lock().await.find_id(&r.sku_id)?.unpack().clone();
+    Ok(sku_obj_with(res, &profile))
+  }
+
+  // Check if tax class exists
+  async fn check_tax_class(&self, tax_class_id: u32) -> ServiceResult<()> {
+    if tax_class_id == 0 {
+      return Err(ServiceError::bad_request(
+        "Az adóosztály megadása kötelező!",
+      ));
+    }
+    self
+      .tax_classes
+      .lock()
+      .await
+      .find_id(&tax_class_id)
+      .map_err(|_| ServiceError::bad_request("A megadott adóosztály nem létezik!"))?;
+    Ok(())
+  }
+
+  // Create new tax class
+  async fn create_tax_class(&self, r: NewTaxClass) -> ServiceResult<TaxClassObj> {
+    let name = r.name.trim().to_string();
+    if name.is_empty() {
+      return Err(ServiceError::bad_request(
+        "Az adóosztály neve nem lehet üres!",
+      ));
+    }
+    let rate = tax::VatRate::try_from_str(&r.rate)?;
+    // Check name is not taken
+    if self
+      .tax_classes
+      .lock()
+      .await
+      .iter()
+      .any(|t| t.unpack().name.to_lowercase() == name.to_lowercase())
+    {
+      return Err(ServiceError::already_exist(
+        "A megadott adóosztály már létezik!",
+      ));
+    }
+    let next_tax_class_id = self.next_tax_class_id().await;
+    let new_tax_class = tax::TaxClass::new(next_tax_class_id, name, rate, r.created_by);
+    self
+      .tax_classes
+      .lock()
+      .await
+      .insert(new_tax_class.clone())?;
+    Ok(new_tax_class.into())
+  }
+
+  // Get all tax classes
+  async fn get_tax_class_all(&self) -> ServiceResult<Vec<TaxClassObj>> {
+    let res = self
+      .tax_classes
+      .lock()
+      .await
+      .iter()
+      .map(|t| t.unpack().clone().into())
+      .collect::<Vec<TaxClassObj>>();
+    Ok(res)
+  }
+
+  // Get tax class by ID
+  async fn get_tax_class(&self, r: GetTaxClassRequest) -> ServiceResult<TaxClassObj> {
+    let res = self
+      .tax_classes
+      .lock()
+      .await
+      .find_id(&r.tax_class_id)?
+      .unpack()
+      .clone();
+    Ok(res.into())
+  }
+
+  // Schedule new rate for a tax class
+  // Rates can only be scheduled for the future
+  async fn schedule_tax_rate(&self, r: ScheduleTaxRateRequest) -> ServiceResult<TaxClassObj> {
+    let rate = tax::VatRate::try_from_str(&r.rate)?;
+    let valid_from = DateTime::parse_from_rfc3339(&r.valid_from)
+      .map_err(|_| ServiceError::bad_request("Hibás érvényesség kezdete!"))?
+      .with_timezone(&Utc);
+    if valid_from <= Utc::now() {
+      return Err(ServiceError::bad_request(
+        "Új ÁFA kulcs csak a jövőre ütemezhető!",
+      ));
+    }
+    let res = self
+      .tax_classes
+      .lock()
+      .await
+      .find_id_mut(&r.tax_class_id)?
+      .as_mut()
+      .unpack()
+      .schedule_rate(rate, valid_from)
+      .clone();
+    Ok(res.into())
+  }
+
+  // Set product tax class and update its SKUs
+  async fn set_product_tax_class(
+    &self,
+    r: SetProductTaxClassRequest,
+    uid: u32,
+  ) -> ServiceResult<ProductObj> {
+    self.check_tax_class(r.tax_class_id).await?;
+    // Update product and its related SKUs
+    let res = self
+      .update_product_and_skus_with(
+        r.product_id,
+        "SetProductTaxClass",
+        uid,
+        |p| {
+          p.set_tax_class(r.tax_class_id);
+          Ok(())
+        },
+        |p, s| {
+          s.update_parent(p);
+          Ok(())
+        },
+      )
+      .await?;
+    Ok(res.into())
+  }
+
+  // Get product change history, newest first
+  // History of deleted products remains readable
+  async fn get_product_history(&self, r: GetHistoryRequest) -> ServiceResult<HistoryPage> {
+    Ok(history_page(&self.product_history, &r).await)
+  }
+
+  // Get SKU change history, newest first
+  // History of deleted SKUs remains readable
+  async fn get_sku_history(&self, r: GetHistoryRequest) -> ServiceResult<HistoryPage> {
+    Ok(history_page(&self.sku_history, &r).await)
+  }
+}
+
+// Append changes to the history of the given record
+// Empty changes are not recorded
+async fn log_history(
+  store: &Mutex<VecPack<history::History>>,
+  id: u32,
+  rpc: &str,
+  uid: u32,
+  changes: Vec<history::FieldChange>,
+) -> ServiceResult<()> {
+  if changes.is_empty() {
+    return Ok(());
+  }
+  let entry = history::HistoryEntry::new(uid, rpc, changes);
+  let mut store = store.lock().await;
+  match store.find_id_mut(&id) {
+    Ok(history) => {
+      history.as_mut().unpack().push(entry);
+    }
+    Err(_) => {
+      let mut history = history::History::new(id);
+      history.push(entry);
+      store.insert(history)?;
+    }
+  }
+  Ok(())
+}
+
+// Get one page of a record history, newest first
+async fn history_page(
+  store: &Mutex<VecPack<history::History>>,
+  r: &GetHistoryRequest,
+) -> HistoryPage {
+  let limit = match r.limit {
+    0 => 50,
+    limit => limit as usize,
+  };
+  match store.lock().await.find_id(&r.id) {
+    Ok(history) => {
+      let entries = &history.unpack().entries;
+      HistoryPage {
+        entries: entries
+          .iter()
+          .rev()
+          .skip(r.offset as usize)
+          .take(limit)
+          .map(|e| e.clone().into())
+          .collect(),
+        total: entries.len() as u32,
+      }
+    }
+    Err(_) => HistoryPage {
+      entries: Vec::new(),
+      total: 0,
+    },
+  }
+}
+
+/// Store the changes of a batch plan
+/// On the first failure the already stored changes are restored
+/// from their old values, so either every change is stored or none
+fn store_batch(
+  products: &mut VecPack<product::Product>,
+  skus: &mut VecPack<product::Sku>,
+  plan: &batch::Plan,
+) -> ServiceResult<()> {
+  let mut stored = (0, 0);
+  let res = store_batch_changes(products, skus, plan, &mut stored);
+  if res.is_err() {
+    for (old, new) in plan.skus[..stored.1].iter().rev() {
+      let restored = match old {
+        Some(old) => skus
+          .find_id_mut(&new.sku)
+          .and_then(|s| s.update(|s| *s = old.clone())),
+        None => skus.remove_pack(&new.sku).map(|_| ()),
+      };
+      if let Err(e) = restored {
+        eprintln!("Error while restoring SKU {}: {}", new.sku, e);
+      }
+    }
+    for (old, new) in plan.products[..stored.0].iter().rev() {
+      let restored = match old {
+        Some(old) => products
+          .find_id_mut(&new.product_id)
+          .and_then(|p| p.update(|p| *p = old.clone())),
+        None => products.remove_pack(&new.product_id).map(|_| ()),
+      };
+      if let Err(e) = restored {
+        eprintln!("Error while restoring product {}: {}", new.product_id, e);
+      }
+    }
+  }
+  res
+}
+
+/// Store the changes of a batch plan in order
+/// and count the stored ones in stored
+fn store_batch_changes(
+  products: &mut VecPack<product::Product>,
+  skus: &mut VecPack<product::Sku>,
+  plan: &batch::Plan,
+  stored: &mut (usize, usize),
+) -> ServiceResult<()> {
+  for (old, new) in &plan.products {
+    match old {
+      Some(_) => products
+        .find_id_mut(&new.product_id)?
+        .update(|p| *p = new.clone())?,
+      None => products.insert(new.clone())?,
+    };
+    stored.0 += 1;
+  }
+  for (old, new) in &plan.skus {
+    match old {
+      Some(_) => skus.find_id_mut(&new.sku)?.update(|s| *s = new.clone())?,
+      None => skus.insert(new.clone())?,
+    };
+    stored.1 += 1;
+  }
+  Ok(())
+}
+
+// Helper to convert proto listing request to listing query
+fn list_query(r: ListRequest) -> ServiceResult<listing::Query> {
+  let sort = listing::SortBy::try_from_str(&r.sort)?;
+  let limit = match r.limit as usize {
+    0 => listing::DEFAULT_LIMIT,
+    limit if limit > listing::MAX_LIMIT => {
+      return Err(ServiceError::bad_request(&format!(
+        "Legfeljebb {} elem kérhető le egyszerre!",
+        listing::MAX_LIMIT
+      )))
+    }
+    limit => limit,
+  };
+  let cursor = match r.cursor.trim().is_empty() {
+    true => None,
+    false => Some(listing::Key::try_from_cursor(r.cursor.trim(), sort)?),
+  };
+  let f = r.filter.unwrap_or_default();
+  let time = |s: &str| -> ServiceResult<Option<DateTime<Utc>>> {
+    match s.trim().is_empty() {
+      true => Ok(None),
+      false => Ok(Some(
+        DateTime::parse_from_rfc3339(s.trim())
+          .map_err(|_| ServiceError::bad_request(&format!("Hibás időpont: {}", s)))?
+          .with_timezone(&Utc),
+      )),
+    }
+  };
+  let filter = listing::Filter {
+    discontinued: f.discontinued,
+    perishable: f.perishable,
+    unit: match f.unit.trim().is_empty() {
+      true => None,
+      false => Some(Unit::try_from_str(&f.unit)?),
+    },
+    created_by: optional_id(f.created_by),
+    created_from: time(&f.created_from)?,
+    created_to: time(&f.created_to)?,
+    product_id: optional_id(f.product_id),
+    include_archived: f.include_archived,
+  };
+  Ok(listing::Query {
+    sort,
+    descending: r.descending,
+    filter,
+    cursor,
+    limit,
+  })
+}
+
+// Helper to convert proto ID to Option
+// 0 means no ID
+fn optional_id(id: u32) -> Option<u32> {
+  match id {
+    0 => None,
+    id => Some(id),
+  }
+}
+
+// Helper to convert proto limit to take count
+// 0 means no limit
+fn limit_of(limit: u32) -> usize {
+  match limit {
+    0 => usize::MAX,
+    limit => limit as usize,
+  }
+}
+
+// Helper to get the product description of a SKU
+fn description_of<'a>(descriptions: &'a HashMap<u32, String>, sku: &product::Sku) -> &'a str {
+  descriptions
+    .get(&sku.product_id)
+    .map(|d| d.as_str())
+    .unwrap_or_default()
+}
+
+// Helper to convert proto attributes to name, value pairs
+fn attribute_pairs(attributes: Vec<SkuAttributeObj>) -> Vec<(String, String)> {
+  attributes.into_iter().map(|a| (a.name, a.value)).collect()
+}
+
+#[tonic::async_trait]
+impl proto::product_server::Product for ProductService {
+  async fn create_product(
+    &self,
+    request: Request<NewProduct>,
+  ) -> Result<Response<ProductObj>, Status> {
+    let res = self.create_product(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn get_product_all(
+    &self,
+    request: Request<GetProductAllRequest>,
+  ) -> Result<Response<ProductIds>, Status> {
+    let res = self.get_product_all(request.into_inner()).await?;
+    Ok(Response::new(ProductIds { product_ids: res }))
+  }
+
+  async fn get_product(
+    &self,
+    request: Request<GetProductRequest>,
+  ) -> Result<Response<ProductObj>, Status> {
+    let res = self.get_product(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
+
+  type GetProductBulkStream = ReceiverStream<Result<ProductObj, Status>>;
+
+  async fn get_product_bulk(
+    &self,
     request: Request<GetProductBulkRequest>,
   ) -> Result<Response<Self::GetProductBulkStream>, Status> {
     // Create channel for stream response
-    let (mut tx, rx) = tokio::sync::mpsc::channel(100);
+    let (mut tx, rx) = tokio::sync::mpsc::channel(100);
+
+    // Get resources as Vec<SourceObject>
+    let res = self.get_product_bulk(request.into_inner()).await?;
+    tokio::spawn(async move {
+      for ots in res.into_iter() {
+        tx.send(Ok(ots)).await.unwrap();
+      }
+    });
+
+    // Send back the receiver
+    Ok(Response::new(ReceiverStream::new(rx)))
+  }
+
+  async fn update_product(
+    &self,
+    request: Request<ProductObj>,
+  ) -> Result<Response<ProductObj>, Status> {
+    let uid = request_uid(&request);
+    let res = self.update_product(request.into_inner(), uid).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn find_product(
+    &self,
+    request: Request<FindProductRequest>,
+  ) -> Result<Response<ProductIds>, Status> {
+    let res = self.find_product(request.into_inner()).await?;
+    Ok(Response::new(ProductIds { product_ids: res }))
+  }
+
+  async fn batch_catalog(
+    &self,
+    request: Request<BatchCatalogRequest>,
+  ) -> Result<Response<BatchCatalogObj>, Status> {
+    let uid = request_uid(&request);
+    let res = self.batch_catalog(request.into_inner(), uid).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn list_products(
+    &self,
+    request: Request<ListRequest>,
+  ) -> Result<Response<ProductPage>, Status> {
+    let res = self.list_products(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn list_skus(&self, request: Request<ListRequest>) -> Result<Response<SkuPage>, Status> {
+    let res = self.list_skus(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn search_product(
+    &self,
+    request: Request<SearchRequest>,
+  ) -> Result<Response<ProductHits>, Status> {
+    let res = self.search_product(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn search_sku(&self, request: Request<SearchRequest>) -> Result<Response<SkuHits>, Status> {
+    let res = self.search_sku(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn create_sku(&self, request: Request<NewSku>) -> Result<Response<SkuObj>, Status> {
+    let res = self.create_sku(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn get_sku_all(
+    &self,
+    request: Request<GetSkuAllRequest>,
+  ) -> Result<Response<SkuIds>, Status> {
+    let res = self.get_sku_all(request.into_inner()).await?;
+    Ok(Response::new(SkuIds { sku_ids: res }))
+  }
+
+  async fn get_sku(&self, request: Request<GetSkuRequest>) -> Result<Response<SkuObj>, Status> {
+    let res = self.get_sku(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
+
+  type GetSkuBulkStream = ReceiverStream<Result<SkuObj, Status>>;
+
+  async fn get_sku_bulk(
+    &self,
+    request: Request<GetSkuBulkRequest>,
+  ) -> Result<Response<Self::GetSkuBulkStream>, Status> {
+    // Create channel for stream response
+    let (mut tx, rx) = tokio::sync::mpsc::channel(100);
+
+    // Get resources as Vec<SourceObject>
+    let res = self.get_sku_bulk(request.into_inner()).await?;
+
+    // Send the result items through the channel
+    tokio::spawn(async move {
+      for ots in res.into_iter() {
+        tx.send(Ok(ots)).await.unwrap();
+      }
+    });
+
+    // Send back the receiver
+    Ok(Response::new(ReceiverStream::new(rx)))
+  }
+
+  async fn update_sku(&self, request: Request<SkuObj>) -> Result<Response<SkuObj>, Status> {
+    let uid = request_uid(&request);
+    let res = self.update_sku(request.into_inner(), uid).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn update_sku_divide(
+    &self,
+    request: Request<UpdateSkuDivideRequest>,
+  ) -> Result<Response<SkuObj>, Status> {
+    let uid = request_uid(&request);
+    let res = self.update_sku_divide(request.into_inner(), uid).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn update_sku_divide_rule(
+    &self,
+    request: Request<UpdateSkuDivideRuleRequest>,
+  ) -> Result<Response<SkuObj>, Status> {
+    let uid = request_uid(&request);
+    let res = self
+      .update_sku_divide_rule(request.into_inner(), uid)
+      .await?;
+    Ok(Response::new(res))
+  }
+
+  async fn check_sku_split(
+    &self,
+    request: Request<CheckSkuSplitRequest>,
+  ) -> Result<Response<SkuSplitObj>, Status> {
+    let res = self.check_sku_split(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn find_sku(&self, request: Request<FindSkuRequest>) -> Result<Response<SkuIds>, Status> {
+    let res = self.find_sku(request.into_inner()).await?;
+    Ok(Response::new(SkuIds { sku_ids: res }))
+  }
+
+  async fn update_product_discontinued(
+    &self,
+    request: Request<UpdateProductDiscontinuedRequest>,
+  ) -> Result<Response<ProductObj>, Status> {
+    let uid = request_uid(&request);
+    let res = self
+      .update_product_discontinued(request.into_inner(), uid)
+      .await?;
+    Ok(Response::new(res))
+  }
+
+  async fn update_product_perishable(
+    &self,
+    request: Request<UpdateProductPerishableRequest>,
+  ) -> Result<Response<ProductObj>, Status> {
+    let uid = request_uid(&request);
+    let res = self
+      .update_product_perishable(request.into_inner(), uid)
+      .await?;
+    Ok(Response::new(res))
+  }
+
+  async fn update_sku_discontinued(
+    &self,
+    request: Request<UpdateSkuDiscontinuedRequest>,
+  ) -> Result<Response<SkuObj>, Status> {
+    let uid = request_uid(&request);
+    let res = self
+      .update_sku_discontinued(request.into_inner(), uid)
+      .await?;
+    Ok(Response::new(res))
+  }
+
+  async fn create_category(
+    &self,
+    request: Request<NewCategory>,
+  ) -> Result<Response<CategoryObj>, Status> {
+    let res = self.create_category(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn rename_category(
+    &self,
+    request: Request<RenameCategoryRequest>,
+  ) -> Result<Response<CategoryObj>, Status> {
+    let res = self.rename_category(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn move_category(
+    &self,
+    request: Request<MoveCategoryRequest>,
+  ) -> Result<Response<CategoryObj>, Status> {
+    let res = self.move_category(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn delete_category(
+    &self,
+    request: Request<DeleteCategoryRequest>,
+  ) -> Result<Response<()>, Status> {
+    self.delete_category(request.into_inner()).await?;
+    Ok(Response::new(()))
+  }
+
+  type GetCategoryTreeStream = ReceiverStream<Result<CategoryObj, Status>>;
+
+  async fn get_category_tree(
+    &self,
+    request: Request<GetCategoryTreeRequest>,
+  ) -> Result<Response<Self::GetCategoryTreeStream>, Status> {
+    // Create channel for stream response
+    let (tx, rx) = tokio::sync::mpsc::channel(100);
 
-    // Get resources as Vec<SourceObject>
-    let res = self.get_product_bulk(request.into_inner()).await?;
+    // Get resources as Vec<CategoryObj>
+    let res = self.get_category_tree(request.into_inner()).await?;
+
+    // Send the result items through the channel
     tokio::spawn(async move {
       for ots in res.into_iter() {
         tx.send(Ok(ots)).await.unwrap();
@@ -432,48 +2737,87 @@ impl gzlib::proto::product::product_server::Product for ProductService {
     Ok(Response::new(ReceiverStream::new(rx)))
   }
 
-  async fn update_product(
+  async fn get_category_products(
     &self,
-    request: Request<ProductObj>,
+    request: Request<GetCategoryProductsRequest>,
+  ) -> Result<Response<ProductIds>, Status> {
+    let res = self.get_category_products(request.into_inner()).await?;
+    Ok(Response::new(ProductIds { product_ids: res }))
+  }
+
+  async fn set_product_categories(
+    &self,
+    request: Request<SetProductCategoriesRequest>,
   ) -> Result<Response<ProductObj>, Status> {
-    let res = self.update_product(request.into_inner()).await?;
+    let uid = request_uid(&request);
+    let res = self
+      .set_product_categories(request.into_inner(), uid)
+      .await?;
     Ok(Response::new(res))
   }
 
-  async fn find_product(
+  async fn add_sku_image(
     &self,
-    request: Request<FindProductRequest>,
-  ) -> Result<Response<SkuIds>, Status> {
-    let res = self.find_product(request.into_inner()).await?;
-    Ok(Response::new(SkuIds { sku_ids: res }))
+    request: Request<NewSkuImage>,
+  ) -> Result<Response<SkuImagesObj>, Status> {
+    let uid = request_uid(&request);
+    let res = self.add_sku_image(request.into_inner(), uid).await?;
+    Ok(Response::new(res))
   }
 
-  async fn create_sku(&self, request: Request<NewSku>) -> Result<Response<SkuObj>, Status> {
-    let res = self.create_sku(request.into_inner()).await?;
+  async fn set_sku_primary_image(
+    &self,
+    request: Request<SetSkuPrimaryImageRequest>,
+  ) -> Result<Response<SkuImagesObj>, Status> {
+    let uid = request_uid(&request);
+    let res = self
+      .set_sku_primary_image(request.into_inner(), uid)
+      .await?;
     Ok(Response::new(res))
   }
 
-  async fn get_sku_all(&self, _: Request<()>) -> Result<Response<SkuIds>, Status> {
-    let res = self.get_sku_all().await?;
-    Ok(Response::new(SkuIds { sku_ids: res }))
+  async fn reorder_sku_images(
+    &self,
+    request: Request<ReorderSkuImagesRequest>,
+  ) -> Result<Response<SkuImagesObj>, Status> {
+    let uid = request_uid(&request);
+    let res = self.reorder_sku_images(request.into_inner(), uid).await?;
+    Ok(Response::new(res))
   }
 
-  async fn get_sku(&self, request: Request<GetSkuRequest>) -> Result<Response<SkuObj>, Status> {
-    let res = self.get_sku(request.into_inner()).await?;
+  async fn delete_sku_image(
+    &self,
+    request: Request<DeleteSkuImageRequest>,
+  ) -> Result<Response<SkuImagesObj>, Status> {
+    let uid = request_uid(&request);
+    let res = self.delete_sku_image(request.into_inner(), uid).await?;
     Ok(Response::new(res))
   }
 
-  type GetSkuBulkStream = ReceiverStream<Result<SkuObj, Status>>;
+  async fn get_sku_images(
+    &self,
+    request: Request<GetSkuRequest>,
+  ) -> Result<Response<SkuImagesObj>, Status> {
+    let res = self.get_sku_images(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
 
-  async fn get_sku_bulk(
+  async fn create_brand(&self, request: Request<NewBrand>) -> Result<Response<BrandObj>, Status> {
+    let res = self.create_brand(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
+
+  type GetBrandAllStream = ReceiverStream<Result<BrandObj, Status>>;
+
+  async fn get_brand_all(
     &self,
-    request: Request<GetSkuBulkRequest>,
-  ) -> Result<Response<Self::GetSkuBulkStream>, Status> {
+    _: Request<()>,
+  ) -> Result<Response<Self::GetBrandAllStream>, Status> {
     // Create channel for stream response
-    let (mut tx, rx) = tokio::sync::mpsc::channel(100);
+    let (tx, rx) = tokio::sync::mpsc::channel(100);
 
-    // Get resources as Vec<SourceObject>
-    let res = self.get_sku_bulk(request.into_inner()).await?;
+    // Get resources as Vec<BrandObj>
+    let res = self.get_brand_all().await?;
 
     // Send the result items through the channel
     tokio::spawn(async move {
@@ -486,64 +2830,431 @@ impl gzlib::proto::product::product_server::Product for ProductService {
     Ok(Response::new(ReceiverStream::new(rx)))
   }
 
-  async fn update_sku(&self, request: Request<SkuObj>) -> Result<Response<SkuObj>, Status> {
-    let res = self.update_sku(request.into_inner()).await?;
+  async fn get_brand(
+    &self,
+    request: Request<GetBrandRequest>,
+  ) -> Result<Response<BrandObj>, Status> {
+    let res = self.get_brand(request.into_inner()).await?;
     Ok(Response::new(res))
   }
 
-  async fn update_sku_divide(
+  async fn update_brand(&self, request: Request<BrandObj>) -> Result<Response<BrandObj>, Status> {
+    let res = self.update_brand(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn delete_brand(&self, request: Request<GetBrandRequest>) -> Result<Response<()>, Status> {
+    self.delete_brand(request.into_inner()).await?;
+    Ok(Response::new(()))
+  }
+
+  async fn create_manufacturer(
     &self,
-    request: Request<UpdateSkuDivideRequest>,
+    request: Request<NewManufacturer>,
+  ) -> Result<Response<ManufacturerObj>, Status> {
+    let res = self.create_manufacturer(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
+
+  type GetManufacturerAllStream = ReceiverStream<Result<ManufacturerObj, Status>>;
+
+  async fn get_manufacturer_all(
+    &self,
+    _: Request<()>,
+  ) -> Result<Response<Self::GetManufacturerAllStream>, Status> {
+    // Create channel for stream response
+    let (tx, rx) = tokio::sync::mpsc::channel(100);
+
+    // Get resources as Vec<ManufacturerObj>
+    let res = self.get_manufacturer_all().await?;
+
+    // Send the result items through the channel
+    tokio::spawn(async move {
+      for ots in res.into_iter() {
+        tx.send(Ok(ots)).await.unwrap();
+      }
+    });
+
+    // Send back the receiver
+    Ok(Response::new(ReceiverStream::new(rx)))
+  }
+
+  async fn get_manufacturer(
+    &self,
+    request: Request<GetManufacturerRequest>,
+  ) -> Result<Response<ManufacturerObj>, Status> {
+    let res = self.get_manufacturer(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn update_manufacturer(
+    &self,
+    request: Request<ManufacturerObj>,
+  ) -> Result<Response<ManufacturerObj>, Status> {
+    let res = self.update_manufacturer(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn delete_manufacturer(
+    &self,
+    request: Request<GetManufacturerRequest>,
+  ) -> Result<Response<()>, Status> {
+    self.delete_manufacturer(request.into_inner()).await?;
+    Ok(Response::new(()))
+  }
+
+  async fn set_product_brand(
+    &self,
+    request: Request<SetProductBrandRequest>,
+  ) -> Result<Response<ProductObj>, Status> {
+    let uid = request_uid(&request);
+    let res = self.set_product_brand(request.into_inner(), uid).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn add_sku_barcode(
+    &self,
+    request: Request<AddSkuBarcodeRequest>,
   ) -> Result<Response<SkuObj>, Status> {
-    let res = self.update_sku_divide(request.into_inner()).await?;
+    let uid = request_uid(&request);
+    let res = self.add_sku_barcode(request.into_inner(), uid).await?;
     Ok(Response::new(res))
   }
 
-  async fn find_sku(&self, request: Request<FindSkuRequest>) -> Result<Response<SkuIds>, Status> {
-    let res = self.find_sku(request.into_inner()).await?;
-    Ok(Response::new(SkuIds { sku_ids: res }))
+  async fn remove_sku_barcode(
+    &self,
+    request: Request<RemoveSkuBarcodeRequest>,
+  ) -> Result<Response<SkuObj>, Status> {
+    let uid = request_uid(&request);
+    let res = self.remove_sku_barcode(request.into_inner(), uid).await?;
+    Ok(Response::new(res))
   }
 
-  async fn update_product_discontinued(
+  async fn get_sku_by_barcode(
     &self,
-    request: Request<UpdateProductDiscontinuedRequest>,
+    request: Request<GetSkuByBarcodeRequest>,
+  ) -> Result<Response<SkuObj>, Status> {
+    let res = self.get_sku_by_barcode(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn archive_product(
+    &self,
+    request: Request<GetProductRequest>,
+  ) -> Result<Response<ProductObj>, Status> {
+    let uid = request_uid(&request);
+    let res = self.archive_product(request.into_inner(), uid).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn restore_product(
+    &self,
+    request: Request<GetProductRequest>,
+  ) -> Result<Response<ProductObj>, Status> {
+    let uid = request_uid(&request);
+    let res = self.restore_product(request.into_inner(), uid).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn delete_product(
+    &self,
+    request: Request<GetProductRequest>,
+  ) -> Result<Response<()>, Status> {
+    let uid = request_uid(&request);
+    self.delete_product(request.into_inner(), uid).await?;
+    Ok(Response::new(()))
+  }
+
+  async fn archive_sku(&self, request: Request<GetSkuRequest>) -> Result<Response<SkuObj>, Status> {
+    let uid = request_uid(&request);
+    let res = self.archive_sku(request.into_inner(), uid).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn restore_sku(&self, request: Request<GetSkuRequest>) -> Result<Response<SkuObj>, Status> {
+    let uid = request_uid(&request);
+    let res = self.restore_sku(request.into_inner(), uid).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn delete_sku(&self, request: Request<GetSkuRequest>) -> Result<Response<()>, Status> {
+    let uid = request_uid(&request);
+    self.delete_sku(request.into_inner(), uid).await?;
+    Ok(Response::new(()))
+  }
+
+  async fn set_product_attribute_schema(
+    &self,
+    request: Request<SetProductAttributeSchemaRequest>,
   ) -> Result<Response<ProductObj>, Status> {
+    let uid = request_uid(&request);
     let res = self
-      .update_product_discontinued(request.into_inner())
+      .set_product_attribute_schema(request.into_inner(), uid)
       .await?;
     Ok(Response::new(res))
   }
 
-  async fn update_product_perishable(
+  async fn set_sku_attributes(
     &self,
-    request: Request<UpdateProductPerishableRequest>,
-  ) -> Result<Response<ProductObj>, Status> {
-    let res = self.update_product_perishable(request.into_inner()).await?;
+    request: Request<SetSkuAttributesRequest>,
+  ) -> Result<Response<SkuObj>, Status> {
+    let uid = request_uid(&request);
+    let res = self.set_sku_attributes(request.into_inner(), uid).await?;
     Ok(Response::new(res))
   }
 
-  async fn update_sku_discontinued(
+  async fn find_sku_by_attributes(
     &self,
-    request: Request<UpdateSkuDiscontinuedRequest>,
+    request: Request<FindSkuByAttributesRequest>,
+  ) -> Result<Response<SkuIds>, Status> {
+    let res = self.find_sku_by_attributes(request.into_inner()).await?;
+    Ok(Response::new(SkuIds { sku_ids: res }))
+  }
+
+  async fn add_product_tags(
+    &self,
+    request: Request<ProductTagsRequest>,
+  ) -> Result<Response<ProductIds>, Status> {
+    let uid = request_uid(&request);
+    let res = self
+      .update_product_tags(request.into_inner(), true, uid)
+      .await?;
+    Ok(Response::new(ProductIds { product_ids: res }))
+  }
+
+  async fn remove_product_tags(
+    &self,
+    request: Request<ProductTagsRequest>,
+  ) -> Result<Response<ProductIds>, Status> {
+    let uid = request_uid(&request);
+    let res = self
+      .update_product_tags(request.into_inner(), false, uid)
+      .await?;
+    Ok(Response::new(ProductIds { product_ids: res }))
+  }
+
+  async fn add_sku_tags(
+    &self,
+    request: Request<SkuTagsRequest>,
+  ) -> Result<Response<SkuIds>, Status> {
+    let uid = request_uid(&request);
+    let res = self
+      .update_sku_tags(request.into_inner(), true, uid)
+      .await?;
+    Ok(Response::new(SkuIds { sku_ids: res }))
+  }
+
+  async fn remove_sku_tags(
+    &self,
+    request: Request<SkuTagsRequest>,
+  ) -> Result<Response<SkuIds>, Status> {
+    let uid = request_uid(&request);
+    let res = self
+      .update_sku_tags(request.into_inner(), false, uid)
+      .await?;
+    Ok(Response::new(SkuIds { sku_ids: res }))
+  }
+
+  type GetTagAllStream = ReceiverStream<Result<TagObj, Status>>;
+
+  async fn get_tag_all(&self, _: Request<()>) -> Result<Response<Self::GetTagAllStream>, Status> {
+    // Create channel for stream response
+    let (tx, rx) = tokio::sync::mpsc::channel(100);
+
+    // Get resources as Vec<TagObj>
+    let res = self.get_tag_all().await?;
+
+    // Send the result items through the channel
+    tokio::spawn(async move {
+      for ots in res.into_iter() {
+        tx.send(Ok(ots)).await.unwrap();
+      }
+    });
+
+    // Send back the receiver
+    Ok(Response::new(ReceiverStream::new(rx)))
+  }
+
+  async fn create_bundle_sku(
+    &self,
+    request: Request<NewBundleSku>,
+  ) -> Result<Response<SkuObj>, Status> {
+    let res = self.create_bundle_sku(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn get_sku_content(
+    &self,
+    request: Request<GetSkuContentRequest>,
+  ) -> Result<Response<SkuContentObj>, Status> {
+    let res = self.get_sku_content(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn get_sku_unit_price(
+    &self,
+    request: Request<GetSkuUnitPriceRequest>,
+  ) -> Result<Response<SkuUnitPriceObj>, Status> {
+    let res = self.get_sku_unit_price(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn set_sku_packaging(
+    &self,
+    request: Request<SetSkuPackagingRequest>,
+  ) -> Result<Response<SkuObj>, Status> {
+    let uid = request_uid(&request);
+    let res = self.set_sku_packaging(request.into_inner(), uid).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn parse_packaging(
+    &self,
+    request: Request<ParsePackagingRequest>,
+  ) -> Result<Response<ParsedPackagingObj>, Status> {
+    let res = self.parse_packaging(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn get_sku_localized(
+    &self,
+    request: Request<GetSkuLocalizedRequest>,
   ) -> Result<Response<SkuObj>, Status> {
-    let res = self.update_sku_discontinued(request.into_inner()).await?;
+    let res = self.get_sku_localized(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn create_tax_class(
+    &self,
+    request: Request<NewTaxClass>,
+  ) -> Result<Response<TaxClassObj>, Status> {
+    let res = self.create_tax_class(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
+
+  type GetTaxClassAllStream = ReceiverStream<Result<TaxClassObj, Status>>;
+
+  async fn get_tax_class_all(
+    &self,
+    _: Request<()>,
+  ) -> Result<Response<Self::GetTaxClassAllStream>, Status> {
+    // Create channel for stream response
+    let (tx, rx) = tokio::sync::mpsc::channel(100);
+
+    // Get resources as Vec<TaxClassObj>
+    let res = self.get_tax_class_all().await?;
+
+    // Send the result items through the channel
+    tokio::spawn(async move {
+      for ots in res.into_iter() {
+        tx.send(Ok(ots)).await.unwrap();
+      }
+    });
+
+    // Send back the receiver
+    Ok(Response::new(ReceiverStream::new(rx)))
+  }
+
+  async fn get_tax_class(
+    &self,
+    request: Request<GetTaxClassRequest>,
+  ) -> Result<Response<TaxClassObj>, Status> {
+    let res = self.get_tax_class(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn schedule_tax_rate(
+    &self,
+    request: Request<ScheduleTaxRateRequest>,
+  ) -> Result<Response<TaxClassObj>, Status> {
+    let res = self.schedule_tax_rate(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn set_product_tax_class(
+    &self,
+    request: Request<SetProductTaxClassRequest>,
+  ) -> Result<Response<ProductObj>, Status> {
+    let uid = request_uid(&request);
+    let res = self
+      .set_product_tax_class(request.into_inner(), uid)
+      .await?;
+    Ok(Response::new(res))
+  }
+
+  async fn get_product_history(
+    &self,
+    request: Request<GetHistoryRequest>,
+  ) -> Result<Response<HistoryPage>, Status> {
+    let res = self.get_product_history(request.into_inner()).await?;
+    Ok(Response::new(res))
+  }
+
+  async fn get_sku_history(
+    &self,
+    request: Request<GetHistoryRequest>,
+  ) -> Result<Response<HistoryPage>, Status> {
+    let res = self.get_sku_history(request.into_inner()).await?;
     Ok(Response::new(res))
   }
 }
 
 #[tokio::main]
 async fn main() -> prelude::ServiceResult<()> {
-  let product_db: VecPack<product::Product> = VecPack::load_or_init(PathBuf::from("data/products"))
-    .expect("Error while loading product storage");
+  let product_db: VecPack<product::Product> =
+    VecPack::try_load_or_init(PathBuf::from("data/products"))
+      .expect("Error while loading product storage");
 
   let sku_db: VecPack<product::Sku> =
-    VecPack::load_or_init(PathBuf::from("data/skus")).expect("Error while loading sku storage");
+    VecPack::try_load_or_init(PathBuf::from("data/skus")).expect("Error while loading sku storage");
+
+  let category_db: VecPack<category::Category> =
+    VecPack::load_or_init(PathBuf::from("data/categories"))
+      .expect("Error while loading category storage");
+
+  let brand_db: VecPack<brand::Brand> =
+    VecPack::load_or_init(PathBuf::from("data/brands")).expect("Error while loading brand storage");
+
+  let manufacturer_db: VecPack<brand::Manufacturer> =
+    VecPack::load_or_init(PathBuf::from("data/manufacturers"))
+      .expect("Error while loading manufacturer storage");
+
+  let tax_class_db: VecPack<tax::TaxClass> =
+    VecPack::load_or_init(PathBuf::from("data/tax_classes"))
+      .expect("Error while loading tax class storage");
+
+  let image_store = sku_image::ImageStore::new(PathBuf::from("data/sku_images"));
+
+  let product_history_db: VecPack<history::History> =
+    VecPack::load_or_init(PathBuf::from("data/product_history"))
+      .expect("Error while loading product history storage");
+
+  let sku_history_db: VecPack<history::History> =
+    VecPack::load_or_init(PathBuf::from("data/sku_history"))
+      .expect("Error while loading sku history storage");
 
   let client_upl = UplClient::connect(service_address("SERVICE_ADDR_UPL"))
     .await
     .expect("Could not connect to image processer service");
 
-  let product_service = ProductService::init(product_db, sku_db, client_upl);
+  let product_service = ProductService::init(
+    product_db,
+    sku_db,
+    category_db,
+    brand_db,
+    manufacturer_db,
+    tax_class_db,
+    image_store,
+    product_history_db,
+    sku_history_db,
+    client_upl,
+  );
+
+  // Migrate stored Float quantities
+  // The service starts even if it fails, Float quantities remain readable
+  if let Err(e) = product_service.migrate_quantities().await {
+    eprintln!("Error while migrating sku quantities: {}", e);
+  }
 
   let addr = env::var("SERVICE_ADDR_PRODUCT")
     .unwrap_or("[::1]:50054".into())
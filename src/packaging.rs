@@ -0,0 +1,189 @@
+// Copyright (C) 2020 Peter Mezei
+//
+// This file is part of Gardenzilla.
+//
+// Gardenzilla is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// Gardenzilla is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Gardenzilla.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::barcode::Barcode;
+use crate::format::FormatProfile;
+use crate::prelude::*;
+use crate::quantity::{fancy_display_with, Decimal, Quantity, Unit};
+use serde::{Deserialize, Serialize};
+
+/// Packaging level above the SKU quantity,
+/// e.g. a carton of 12 packs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PackagingLevel {
+  /// Level name, e.g. karton or raklap
+  pub name: String,
+  /// Count of the level below
+  pub count: u32,
+  /// Own barcode of the level
+  pub barcode: Option<Barcode>,
+}
+
+impl std::fmt::Display for PackagingLevel {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match &self.barcode {
+      Some(barcode) => write!(f, "{} ({}x, {})", self.name, self.count, barcode.code),
+      None => write!(f, "{} ({}x)", self.name, self.count),
+    }
+  }
+}
+
+/// Validate packaging levels, innermost first
+pub fn validate(levels: &[PackagingLevel]) -> Result<(), String> {
+  for (index, level) in levels.iter().enumerate() {
+    if level.name.trim().is_empty() {
+      return Err("A csomagolási szint neve nem lehet üres!".to_string());
+    }
+    if level.count == 0 {
+      return Err(format!(
+        "A csomagolási szint darabszáma nem lehet 0: {}",
+        level.name
+      ));
+    }
+    if let Some(barcode) = &level.barcode {
+      if levels[..index]
+        .iter()
+        .filter_map(|l| l.barcode.as_ref())
+        .any(|b| b.index_key() == barcode.index_key())
+      {
+        return Err(format!("Ismétlődő vonalkód: {}", barcode.code));
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Parse packaging expression, e.g. 12x(3x500 g)
+/// Returns the level counts outermost first and the
+/// innermost quantity in the product unit
+pub fn parse(s: &str, unit: &Unit) -> ServiceResult<(Vec<u32>, Quantity)> {
+  let s = s.trim();
+  if !s.ends_with(')') {
+    if s.contains('(') {
+      return Err(ServiceError::bad_request(&format!(
+        "Hiányzó záró zárójel: '{}'",
+        s
+      )));
+    }
+    return Ok((Vec::new(), Quantity::try_from_str_in(s, unit)?));
+  }
+  let open = s
+    .find('(')
+    .ok_or_else(|| ServiceError::bad_request(&format!("Hiányzó nyitó zárójel: '{}'", s)))?;
+  let count = s[..open]
+    .trim()
+    .strip_suffix(['x', 'X', '×'])
+    .and_then(|c| c.trim().parse::<u32>().ok())
+    .ok_or_else(|| {
+      ServiceError::bad_request(&format!(
+        "Hibás csomagolási szorzó: '{}'. Pl.: 12x(3x500 g)",
+        s[..open].trim()
+      ))
+    })?;
+  let (mut counts, quantity) = parse(&s[open + 1..s.len() - 1], unit)?;
+  counts.insert(0, count);
+  Ok((counts, quantity))
+}
+
+/// Display packaging using the given format profile
+pub fn display_with(
+  counts: &[u32],
+  quantity: &Quantity,
+  unit: &Unit,
+  profile: &FormatProfile,
+) -> String {
+  counts.iter().rev().fold(
+    fancy_display_with(quantity, unit, profile),
+    |inner, count| format!("{}x({})", profile.number(&count.to_string()), inner),
+  )
+}
+
+/// Total content of the packaging in base units
+/// Returns error if it is too large to represent
+pub fn total(counts: &[u32], quantity: &Quantity) -> Result<Decimal, String> {
+  counts
+    .iter()
+    .try_fold(quantity.clone(), |q, count| q.checked_mul(*count))
+    .map(|q| q.total())
+    .ok_or_else(|| "A csomagolás teljes mennyisége túl nagy!".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Display packaging by the default profile, e.g. 12x(3x500 g)
+  fn display(counts: &[u32], quantity: &Quantity, unit: &Unit) -> String {
+    display_with(counts, quantity, unit, &FormatProfile::default())
+  }
+
+  #[test]
+  fn test_parse() {
+    assert_eq!(
+      parse("12x(3x500 g)", &Unit::Gram).unwrap(),
+      (vec![12], Quantity::Complex(3, 500))
+    );
+    assert_eq!(
+      parse(" 40 × ( 12x(3x0,5 kg) ) ", &Unit::Gram).unwrap(),
+      (vec![40, 12], Quantity::Complex(3, 500))
+    );
+    assert_eq!(
+      parse("500", &Unit::Gram).unwrap(),
+      (vec![], Quantity::Simple(500))
+    );
+    assert!(parse("12x(3x500 g", &Unit::Gram).is_err());
+    assert!(parse("12x3x500 g)", &Unit::Gram).is_err());
+    assert!(parse("12(500 g)", &Unit::Gram).is_err());
+    assert!(parse("ax(500 g)", &Unit::Gram).is_err());
+    assert!(parse("12x(500 l)", &Unit::Gram).is_err());
+  }
+
+  #[test]
+  fn test_display_and_total() {
+    let quantity = Quantity::Complex(3, 500);
+    assert_eq!(display(&[12], &quantity, &Unit::Gram), "12x(3x500 g)");
+    assert_eq!(
+      display(&[40, 12], &quantity, &Unit::Gram),
+      "40x(12x(3x500 g))"
+    );
+    assert_eq!(display(&[], &quantity, &Unit::Gram), "3x500 g");
+    assert_eq!(total(&[40, 12], &quantity).unwrap(), Decimal::from(720_000));
+    assert!(total(&[u32::MAX, u32::MAX], &quantity).is_err());
+    // Display must parse back
+    assert_eq!(
+      parse(&display(&[40, 12], &quantity, &Unit::Gram), &Unit::Gram).unwrap(),
+      (vec![40, 12], quantity)
+    );
+  }
+
+  #[test]
+  fn test_validate() {
+    let level = |name: &str, count: u32, code: Option<&str>| PackagingLevel {
+      name: name.to_string(),
+      count,
+      barcode: code.map(|c| Barcode::try_new(c, None).unwrap()),
+    };
+    assert!(validate(&[level("karton", 12, None), level("raklap", 40, None)]).is_ok());
+    assert!(validate(&[level("", 12, None)]).is_err());
+    assert!(validate(&[level("karton", 0, None)]).is_err());
+    assert!(validate(&[
+      level("karton", 12, Some("5901234123457")),
+      level("raklap", 40, Some("5901234123457"))
+    ])
+    .is_err());
+  }
+}
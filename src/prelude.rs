@@ -1,10 +1,21 @@
-use gzlib::proto::product::{ProductObj, SkuObj};
+use crate::format::FormatProfile;
+use crate::proto::{
+  AttributeDefObj, BarcodeObj, BrandObj, BundleItemObj, CategoryObj, DivideRuleObj, FieldChangeObj,
+  HistoryEntryObj, ManufacturerObj, PackagingLevelObj, ProductObj, SkuAttributeObj, SkuImagesObj,
+  SkuObj, TaxClassObj, TaxRateObj, ValidationReport, ViolationObj,
+};
+use crate::validate::Violation;
+use tonic::Request;
 
 pub enum ServiceError {
   InternalError(String),
   NotFound(String),
   AlreadyExists(String),
   BadRequest(String),
+  /// Stale record version in an update
+  Conflict(String),
+  /// Every violated Product or Sku invariant
+  Invalid(Vec<Violation>),
 }
 
 impl ServiceError {
@@ -20,6 +31,9 @@ impl ServiceError {
   pub fn bad_request(msg: &str) -> Self {
     ServiceError::BadRequest(msg.to_string())
   }
+  pub fn conflict(msg: &str) -> Self {
+    ServiceError::Conflict(msg.to_string())
+  }
 }
 
 impl std::fmt::Display for ServiceError {
@@ -29,6 +43,16 @@ impl std::fmt::Display for ServiceError {
       ServiceError::NotFound(msg) => write!(f, "{}", msg),
       ServiceError::AlreadyExists(msg) => write!(f, "{}", msg),
       ServiceError::BadRequest(msg) => write!(f, "{}", msg),
+      ServiceError::Conflict(msg) => write!(f, "{}", msg),
+      ServiceError::Invalid(violations) => write!(
+        f,
+        "{}",
+        violations
+          .iter()
+          .map(|v| v.to_string())
+          .collect::<Vec<String>>()
+          .join("; ")
+      ),
     }
   }
 }
@@ -49,6 +73,27 @@ impl From<ServiceError> for ::tonic::Status {
       ServiceError::NotFound(msg) => ::tonic::Status::not_found(msg),
       ServiceError::AlreadyExists(msg) => ::tonic::Status::already_exists(msg),
       ServiceError::BadRequest(msg) => ::tonic::Status::invalid_argument(msg),
+      ServiceError::Conflict(msg) => ::tonic::Status::aborted(msg),
+      // Violations are sent as an encoded ValidationReport in the status details
+      ServiceError::Invalid(violations) => {
+        let msg = ServiceError::Invalid(violations.clone()).to_string();
+        let report = ValidationReport {
+          violations: violations
+            .into_iter()
+            .map(|v| ViolationObj {
+              field: v.field,
+              message: v.message,
+            })
+            .collect(),
+        };
+        let mut details = Vec::new();
+        match ::prost::Message::encode(&report, &mut details) {
+          Ok(_) => {
+            ::tonic::Status::with_details(::tonic::Code::InvalidArgument, msg, details.into())
+          }
+          Err(_) => ::tonic::Status::invalid_argument(msg),
+        }
+      }
     }
   }
 }
@@ -64,6 +109,23 @@ impl From<::packman::PackError> for ServiceError {
 
 pub type ServiceResult<T> = Result<T, ServiceError>;
 
+/// Check the version an update is based on
+/// The version is required, stored records start from 1
+pub fn check_version(current: u32, requested: u32) -> ServiceResult<()> {
+  if requested == 0 {
+    return Err(ServiceError::bad_request(
+      "A módosításhoz meg kell adni az adat verzióját!",
+    ));
+  }
+  match requested == current {
+    true => Ok(()),
+    false => Err(ServiceError::conflict(&format!(
+      "Az adatot időközben módosították (verzió: {}, aktuális: {}). Töltse be újra!",
+      requested, current
+    ))),
+  }
+}
+
 impl From<std::env::VarError> for ServiceError {
   fn from(error: std::env::VarError) -> Self {
     ServiceError::internal_error(&format!("ENV KEY NOT FOUND. {}", error))
@@ -82,31 +144,242 @@ impl From<crate::product::Product> for ProductObj {
       perishable: p.perishable,
       created_by: p.created_by,
       created_at: p.created_at.to_rfc3339(),
+      categories: p.categories,
+      brand_id: p.brand.unwrap_or(0),
+      manufacturer_id: p.manufacturer.unwrap_or(0),
+      archived: p.archived,
+      attribute_schema: p.attributes.into_iter().map(|a| a.into()).collect(),
+      tags: p.tags,
+      tax_class_id: p.tax_class.unwrap_or(0),
+      version: p.version,
     }
   }
 }
 
 impl From<crate::product::Sku> for SkuObj {
   fn from(s: crate::product::Sku) -> Self {
-    let divisible_amount = s.get_divisible_amount();
+    sku_obj_with(s, &FormatProfile::default())
+  }
+}
+
+/// SkuObj with display strings by the given format profile
+/// Stored display strings are made by the default profile
+pub fn sku_obj_with(mut s: crate::product::Sku, profile: &FormatProfile) -> SkuObj {
+  if *profile != FormatProfile::default() {
+    s.localize(profile);
+  }
+  let divisible_amount = s.get_divisible_amount();
+  let component_discontinued = s.has_discontinued_component();
+  let comparison = crate::convert::comparison_quantity(&s.quantity, &s.unit).ok();
+  // Display and total are computed from the levels up to the index
+  let packaging = s
+    .packaging
+    .iter()
+    .enumerate()
+    .map(|(i, level)| PackagingLevelObj {
+      name: level.name.clone(),
+      count: level.count,
+      barcode: level
+        .barcode
+        .as_ref()
+        .map(|b| b.code.clone())
+        .unwrap_or_default(),
+      barcode_kind: level
+        .barcode
+        .as_ref()
+        .map(|b| b.kind.to_string())
+        .unwrap_or_default(),
+      display: s.packaging_display(i, profile),
+      total: s
+        .packaging_total(i)
+        .map(|t| t.to_string())
+        .unwrap_or_default(),
+    })
+    .collect();
+  SkuObj {
+    sku: s.sku,
+    product_id: s.product_id,
+    subname: s.sub_name,
+    display_name: s.display_name,
+    display_packaging: s.display_packaging,
+    quantity: s.quantity.to_string(),
+    unit: s.unit.to_string(),
+    can_divide: s.can_divide,
+    divisible_amount,
+    discontinued: s.discontinued,
+    perishable: s.perishable,
+    created_by: s.created_by,
+    created_at: s.created_at.to_rfc3339(),
+    image_ids: s.images,
+    primary_image_id: s.primary_image.unwrap_or_default(),
+    barcodes: s.barcodes.into_iter().map(|b| b.into()).collect(),
+    archived: s.archived,
+    attributes: s.attributes.into_iter().map(|a| a.into()).collect(),
+    tags: s.tags,
+    tax_class_id: s.tax_class.unwrap_or(0),
+    bundle_items: s.bundle.into_iter().map(|i| i.into()).collect(),
+    component_discontinued,
+    comparison_quantity: comparison
+      .as_ref()
+      .map(|c| c.amount.to_string())
+      .unwrap_or_default(),
+    comparison_unit: crate::convert::reference_unit(&s.unit).to_string(),
+    packaging,
+    divide_rule: Some(s.divide_rule.into()),
+    version: s.version,
+  }
+}
+
+impl From<crate::divide::DivideRule> for DivideRuleObj {
+  fn from(r: crate::divide::DivideRule) -> Self {
+    Self {
+      step: r.step.to_string(),
+      min_remaining: r.min_remaining.to_string(),
+      fractions: r.fractions.iter().map(|f| f.to_string()).collect(),
+    }
+  }
+}
+
+impl From<crate::barcode::Barcode> for BarcodeObj {
+  fn from(b: crate::barcode::Barcode) -> Self {
+    Self {
+      barcode: b.code,
+      kind: b.kind.to_string(),
+    }
+  }
+}
+
+impl From<crate::attribute::AttributeDef> for AttributeDefObj {
+  fn from(a: crate::attribute::AttributeDef) -> Self {
+    Self {
+      name: a.name,
+      kind: a.kind.to_string(),
+      options: a.kind.options(),
+      required: a.required,
+      show_in_name: a.show_in_name,
+    }
+  }
+}
+
+impl From<crate::attribute::AttributeValue> for SkuAttributeObj {
+  fn from(a: crate::attribute::AttributeValue) -> Self {
+    Self {
+      name: a.name,
+      value: a.value,
+    }
+  }
+}
+
+impl From<crate::tax::TaxClass> for TaxClassObj {
+  fn from(t: crate::tax::TaxClass) -> Self {
+    Self {
+      tax_class_id: t.tax_class_id,
+      name: t.name.clone(),
+      current_rate: t
+        .rate_at(chrono::Utc::now())
+        .map(|r| r.to_string())
+        .unwrap_or_default(),
+      rates: t
+        .rates
+        .into_iter()
+        .map(|r| TaxRateObj {
+          rate: r.rate.to_string(),
+          valid_from: r.valid_from.to_rfc3339(),
+        })
+        .collect(),
+      created_by: t.created_by,
+      created_at: t.created_at.to_rfc3339(),
+    }
+  }
+}
+
+impl From<crate::bundle::BundleItem> for BundleItemObj {
+  fn from(i: crate::bundle::BundleItem) -> Self {
+    Self {
+      sku: i.sku,
+      quantity: i.quantity,
+      display_name: i.display_name,
+      discontinued: i.discontinued,
+    }
+  }
+}
+
+impl From<crate::product::Sku> for SkuImagesObj {
+  fn from(s: crate::product::Sku) -> Self {
     Self {
       sku: s.sku,
-      product_id: s.product_id,
-      subname: s.sub_name,
-      display_name: s.display_name,
-      display_packaging: s.display_packaging,
-      quantity: s.quantity.to_string(),
-      unit: s.unit.to_string(),
-      can_divide: s.can_divide,
-      divisible_amount: divisible_amount,
-      discontinued: s.discontinued,
-      perishable: s.perishable,
-      created_by: s.created_by,
-      created_at: s.created_at.to_rfc3339(),
+      primary_image_id: s.primary_image.unwrap_or_default(),
+      image_ids: s.images,
+    }
+  }
+}
+
+impl From<crate::category::Category> for CategoryObj {
+  fn from(c: crate::category::Category) -> Self {
+    Self {
+      category_id: c.category_id,
+      parent_id: c.parent.unwrap_or(0),
+      name: c.name,
+      created_by: c.created_by,
+      created_at: c.created_at.to_rfc3339(),
+    }
+  }
+}
+
+impl From<crate::brand::Brand> for BrandObj {
+  fn from(b: crate::brand::Brand) -> Self {
+    Self {
+      brand_id: b.brand_id,
+      name: b.name,
+      description: b.description,
+      created_by: b.created_by,
+      created_at: b.created_at.to_rfc3339(),
+    }
+  }
+}
+
+impl From<crate::brand::Manufacturer> for ManufacturerObj {
+  fn from(m: crate::brand::Manufacturer) -> Self {
+    Self {
+      manufacturer_id: m.manufacturer_id,
+      name: m.name,
+      description: m.description,
+      created_by: m.created_by,
+      created_at: m.created_at.to_rfc3339(),
+    }
+  }
+}
+
+impl From<crate::history::HistoryEntry> for HistoryEntryObj {
+  fn from(e: crate::history::HistoryEntry) -> Self {
+    Self {
+      changed_by: e.changed_by,
+      changed_at: e.changed_at.to_rfc3339(),
+      rpc: e.rpc,
+      changes: e
+        .changes
+        .into_iter()
+        .map(|c| FieldChangeObj {
+          field: c.field,
+          old_value: c.old_value,
+          new_value: c.new_value,
+        })
+        .collect(),
     }
   }
 }
 
+// Helper to get caller UID from request metadata
+// Returns 0 if it is missing or invalid
+pub fn request_uid<T>(request: &Request<T>) -> u32 {
+  request
+    .metadata()
+    .get("uid")
+    .and_then(|uid| uid.to_str().ok())
+    .and_then(|uid| uid.trim().parse::<u32>().ok())
+    .unwrap_or(0)
+}
+
 // Helper to load service address from env
 pub fn service_address(service_name: &'static str) -> String {
   let addr = std::env::var(service_name).expect(&format!(
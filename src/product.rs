@@ -15,7 +15,14 @@
 // You should have received a copy of the GNU General Public License
 // along with Gardenzilla.  If not, see <http://www.gnu.org/licenses/>.
 
+use crate::attribute::{self, AttributeDef, AttributeValue};
+use crate::barcode::Barcode;
+use crate::bundle::{self, BundleItem};
+use crate::divide::DivideRule;
+use crate::format::FormatProfile;
+use crate::packaging::{self, PackagingLevel};
 use crate::quantity::*;
+use crate::tag;
 use chrono::prelude::*;
 use packman::*;
 use serde::{Deserialize, Serialize};
@@ -41,6 +48,23 @@ pub struct Product {
   pub created_by: u32,
   /// Created at
   pub created_at: DateTime<Utc>,
+  /// Related category IDs
+  pub categories: Vec<u32>,
+  /// Related brand ID
+  pub brand: Option<u32>,
+  /// Related manufacturer ID
+  pub manufacturer: Option<u32>,
+  /// Archived products are hidden from lists and searches
+  pub archived: bool,
+  /// Attribute schema of the related SKUs
+  pub attributes: Vec<AttributeDef>,
+  /// Normalized tags in sorted order
+  pub tags: Vec<String>,
+  /// Related tax class ID
+  /// Required for new products, None only for migrated ones
+  pub tax_class: Option<u32>,
+  /// Record version, increased by every stored change
+  pub version: u32,
 }
 
 impl Product {
@@ -62,6 +86,14 @@ impl Product {
       perishable: false,
       created_by,
       created_at: Utc::now(),
+      categories: Vec::new(),
+      brand: None,
+      manufacturer: None,
+      archived: false,
+      attributes: Vec::new(),
+      tags: Vec::new(),
+      tax_class: None,
+      version: 1,
     }
   }
   /// Update product data
@@ -71,11 +103,25 @@ impl Product {
     self.unit = unit;
     self
   }
+  /// Increase version, called before every stored change
+  pub fn bump_version(&mut self) {
+    self.version += 1;
+  }
   // Add related SKU
   pub fn add_sku(&mut self, sku: u32) -> &Self {
     self.skus.push(sku);
     self
   }
+  // Remove related SKU
+  pub fn remove_sku(&mut self, sku: u32) -> &Self {
+    self.skus.retain(|s| *s != sku);
+    self
+  }
+  // Set archived
+  pub fn set_archived(&mut self, archived: bool) -> &Self {
+    self.archived = archived;
+    self
+  }
   // Set discontinued
   pub fn set_discontinued(&mut self, discontinued: bool) -> &Self {
     self.discontinued = discontinued;
@@ -86,6 +132,40 @@ impl Product {
     self.perishable = perishable;
     self
   }
+  /// Set related categories
+  pub fn set_categories(&mut self, categories: Vec<u32>) -> &Self {
+    self.categories = categories;
+    self
+  }
+  /// Set related brand and manufacturer
+  pub fn set_brand(&mut self, brand: Option<u32>, manufacturer: Option<u32>) -> &Self {
+    self.brand = brand;
+    self.manufacturer = manufacturer;
+    self
+  }
+  /// Set SKU attribute schema
+  /// Related SKUs must be synced by the caller
+  pub fn set_attribute_schema(&mut self, attributes: Vec<AttributeDef>) -> Result<&Self, String> {
+    attribute::validate_schema(&attributes)?;
+    self.attributes = attributes;
+    Ok(self)
+  }
+  /// Add normalized tags
+  pub fn add_tags(&mut self, tags: &[String]) -> &Self {
+    tag::add(&mut self.tags, tags);
+    self
+  }
+  /// Remove normalized tags
+  pub fn remove_tags(&mut self, tags: &[String]) -> &Self {
+    self.tags.retain(|t| !tags.contains(t));
+    self
+  }
+  /// Set related tax class
+  /// Related SKUs must be updated by the caller
+  pub fn set_tax_class(&mut self, tax_class: u32) -> &Self {
+    self.tax_class = Some(tax_class);
+    self
+  }
 }
 
 impl Default for Product {
@@ -100,12 +180,78 @@ impl Default for Product {
       perishable: false,
       created_by: 0,
       created_at: Utc::now(),
+      categories: Vec::new(),
+      brand: None,
+      manufacturer: None,
+      archived: false,
+      attributes: Vec::new(),
+      tags: Vec::new(),
+      tax_class: None,
+      version: 1,
     }
   }
 }
 
 impl TryFrom for Product {
-  type TryFrom = Product;
+  type TryFrom = ProductOld;
+}
+
+/// Frozen baseline Product layout
+/// Used to migrate stored products, only this exact layout is loadable.
+/// bincode is not self-describing, so adding fields, even at the end,
+/// breaks loading. Every future Product layout change needs its own
+/// old struct and migration.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProductOld {
+  pub product_id: u32,
+  pub name: String,
+  pub description: String,
+  pub unit: Unit,
+  pub discontinued: bool,
+  pub perishable: bool,
+  pub skus: Vec<u32>,
+  pub created_by: u32,
+  pub created_at: DateTime<Utc>,
+}
+
+impl Default for ProductOld {
+  fn default() -> Self {
+    Self {
+      product_id: 0,
+      name: String::default(),
+      description: String::default(),
+      unit: Unit::Milliliter,
+      discontinued: false,
+      perishable: false,
+      skus: Vec::new(),
+      created_by: 0,
+      created_at: Utc::now(),
+    }
+  }
+}
+
+impl From<ProductOld> for Product {
+  fn from(p: ProductOld) -> Self {
+    Self {
+      product_id: p.product_id,
+      name: p.name,
+      description: p.description,
+      unit: p.unit,
+      discontinued: p.discontinued,
+      perishable: p.perishable,
+      skus: p.skus,
+      created_by: p.created_by,
+      created_at: p.created_at,
+      categories: Vec::new(),
+      brand: None,
+      manufacturer: None,
+      archived: false,
+      attributes: Vec::new(),
+      tags: Vec::new(),
+      tax_class: None,
+      version: 1,
+    }
+  }
 }
 
 impl VecPackMember for Product {
@@ -145,6 +291,34 @@ pub struct Sku {
   pub created_by: u32,
   // Created at
   pub created_at: DateTime<Utc>,
+  // Image IDs in display order
+  pub images: Vec<String>,
+  // Primary image ID
+  pub primary_image: Option<String>,
+  // Related barcodes
+  pub barcodes: Vec<Barcode>,
+  // Archived SKUs are hidden from lists and searches
+  pub archived: bool,
+  // Attribute values ordered by the product attribute schema
+  pub attributes: Vec<AttributeValue>,
+  // Normalized tags in sorted order
+  pub tags: Vec<String>,
+  // Tax class ID inherited from the related product
+  pub tax_class: Option<u32>,
+  // Bundle components
+  // Empty if SKU is not a bundle
+  pub bundle: Vec<BundleItem>,
+  // Packaging levels above the SKU quantity
+  // innermost first, e.g. karton, raklap
+  pub packaging: Vec<PackagingLevel>,
+  // Split rule of an opened SKU
+  // Only used if can_divide
+  pub divide_rule: DivideRule,
+  // Record version, increased by every stored change
+  pub version: u32,
+  // Archived together with its product, not on its own
+  // Only these SKUs are restored with the product
+  pub archived_by_product: bool,
 }
 
 impl Sku {
@@ -166,10 +340,23 @@ impl Sku {
       quantity,
       unit: parent.unit.clone(),
       can_divide: false,
-      discontinued: false,
+      // SKUs of a discontinued product must be discontinued
+      discontinued: parent.discontinued,
       perishable: false,
       created_by,
       created_at: Utc::now(),
+      images: Vec::new(),
+      primary_image: None,
+      barcodes: Vec::new(),
+      archived: false,
+      attributes: Vec::new(),
+      tags: Vec::new(),
+      tax_class: parent.tax_class,
+      bundle: Vec::new(),
+      packaging: Vec::new(),
+      divide_rule: DivideRule::default(),
+      version: 1,
+      archived_by_product: false,
     };
     res.reset();
     res
@@ -178,13 +365,146 @@ impl Sku {
   pub fn update_parent(&mut self, parent: &Product) -> &Self {
     self.parent_name = parent.name.clone();
     self.unit = parent.unit.clone();
+    self.tax_class = parent.tax_class;
+    self.reset();
+    self
+  }
+  /// Set attribute values validated by the parent attribute schema
+  pub fn set_attributes(
+    &mut self,
+    schema: &[AttributeDef],
+    values: Vec<(String, String)>,
+  ) -> Result<&Self, String> {
+    self.attributes = attribute::validate_values(schema, values)?;
+    self.reset();
+    Ok(self)
+  }
+  /// Revalidate stored attribute values by a new attribute schema
+  /// Values of removed attributes are dropped
+  pub fn sync_attributes(&mut self, schema: &[AttributeDef]) -> Result<&Self, String> {
+    let values = self
+      .attributes
+      .iter()
+      .filter(|a| {
+        schema
+          .iter()
+          .any(|d| d.name.to_lowercase() == a.name.to_lowercase())
+      })
+      .map(|a| (a.name.clone(), a.value.clone()))
+      .collect();
+    self.set_attributes(schema, values)
+  }
+  /// Add normalized tags
+  pub fn add_tags(&mut self, tags: &[String]) -> &Self {
+    tag::add(&mut self.tags, tags);
+    self
+  }
+  /// Remove normalized tags
+  pub fn remove_tags(&mut self, tags: &[String]) -> &Self {
+    self.tags.retain(|t| !tags.contains(t));
+    self
+  }
+  /// Set bundle components
+  pub fn set_bundle(&mut self, items: Vec<BundleItem>) -> &Self {
+    self.bundle = items;
+    self.can_divide = false;
     self.reset();
     self
   }
+  /// Check if SKU is a bundle
+  pub fn is_bundle(&self) -> bool {
+    !self.bundle.is_empty()
+  }
+  /// Check if any bundle component is discontinued
+  pub fn has_discontinued_component(&self) -> bool {
+    self.bundle.iter().any(|i| i.discontinued)
+  }
+  /// Refresh bundle component snapshots
+  /// Missing components are kept as they are
+  pub fn sync_bundle<'a, F>(&mut self, find: F) -> &Self
+  where
+    F: Fn(u32) -> Option<&'a Sku>,
+  {
+    let items = self
+      .bundle
+      .iter()
+      .map(|i| match find(i.sku) {
+        Some(component) => BundleItem::new(component, i.quantity),
+        None => i.clone(),
+      })
+      .collect();
+    self.set_bundle(items)
+  }
+  /// Set packaging levels, innermost first
+  /// Global barcode uniqueness must be checked by the caller
+  pub fn set_packaging(&mut self, levels: Vec<PackagingLevel>) -> Result<&Self, String> {
+    packaging::validate(&levels)?;
+    // Inner levels are part of the outermost one, so they fit too
+    packaging::total(
+      &levels.iter().map(|l| l.count).collect::<Vec<u32>>(),
+      &self.quantity,
+    )?;
+    if let Some(barcode) = levels
+      .iter()
+      .filter_map(|l| l.barcode.as_ref())
+      .find(|b| self.barcodes.iter().any(|c| c.index_key() == b.index_key()))
+    {
+      return Err(format!(
+        "A vonalkód már a SKU-hoz tartozik: {}",
+        barcode.code
+      ));
+    }
+    self.packaging = levels;
+    Ok(self)
+  }
+  /// Level counts of the given packaging level, outermost first
+  fn packaging_counts(&self, level: usize) -> Vec<u32> {
+    self.packaging[..=level]
+      .iter()
+      .rev()
+      .map(|l| l.count)
+      .collect()
+  }
+  /// Packaging level display, e.g. 12x(3x500 g)
+  pub fn packaging_display(&self, level: usize, profile: &FormatProfile) -> String {
+    packaging::display_with(
+      &self.packaging_counts(level),
+      &self.quantity,
+      &self.unit,
+      profile,
+    )
+  }
+  /// Total content of a packaging level in base units
+  /// Returns error if it is too large to represent
+  pub fn packaging_total(&self, level: usize) -> Result<Decimal, String> {
+    packaging::total(&self.packaging_counts(level), &self.quantity)
+  }
+  /// SKU and packaging level barcodes
+  pub fn all_barcodes(&self) -> Vec<&Barcode> {
+    self
+      .barcodes
+      .iter()
+      .chain(self.packaging.iter().filter_map(|l| l.barcode.as_ref()))
+      .collect()
+  }
+  /// Check if SKU has all the given attribute values
+  /// Names and values are case insensitive
+  pub fn has_attributes(&self, values: &[(String, String)]) -> bool {
+    values.iter().all(|(name, value)| {
+      self.attributes.iter().any(|a| {
+        a.name.to_lowercase() == name.trim().to_lowercase()
+          && a.value.to_lowercase() == value.trim().to_lowercase()
+      })
+    })
+  }
   /// Update SKU data
+  /// Divide rule is reset if it is not valid for the new quantity
   pub fn update(&mut self, sub_name: String, quantity: Quantity) -> &Self {
     self.sub_name = sub_name;
     self.quantity = quantity;
+    if self.divide_rule.validate(&self.quantity).is_err() {
+      self.divide_rule = DivideRule::default();
+    }
     self.reset();
     self
   }
@@ -196,6 +516,10 @@ impl Sku {
       self.can_divide = false;
       return Ok(self);
     }
+    // Bundles cannot be divided
+    if self.is_bundle() {
+      return Err("Csomag nem lehet osztható!".to_string());
+    }
     // If can_divide true,
     // we check if quantity is Simple, then set it to true
     // otherwise return error
@@ -207,6 +531,22 @@ impl Sku {
       _ => Err("Csak egyszerű mennyiség lehet osztható!".to_string()),
     }
   }
+  /// Set split rule validated by the SKU quantity
+  pub fn set_divide_rule(&mut self, rule: DivideRule) -> Result<&Self, String> {
+    rule.validate(&self.quantity)?;
+    self.divide_rule = rule;
+    Ok(self)
+  }
+  /// Check if amount can be split from an opened SKU
+  /// Returns the remaining amount
+  pub fn check_split(&self, amount: &Quantity) -> Result<Decimal, String> {
+    if !self.can_divide {
+      return Err("A SKU nem osztható!".to_string());
+    }
+    self
+      .divide_rule
+      .check(self.quantity.total(), amount.total())
+  }
   // Set discontinued
   pub fn set_discontinued(&mut self, discontinued: bool) -> &Self {
     self.discontinued = discontinued;
@@ -217,6 +557,97 @@ impl Sku {
     self.perishable = perishable;
     self
   }
+  // Set archived
+  pub fn set_archived(&mut self, archived: bool) -> &Self {
+    self.archived = archived;
+    self.archived_by_product = false;
+    self
+  }
+  /// Archive together with the related product
+  /// Already archived SKUs keep their own state
+  pub fn archive_with_product(&mut self) -> &Self {
+    if !self.archived {
+      self.archived = true;
+      self.archived_by_product = true;
+    }
+    self
+  }
+  /// Restore together with the related product
+  /// SKUs archived on their own remain archived
+  pub fn restore_with_product(&mut self) -> &Self {
+    if self.archived_by_product {
+      self.archived = false;
+      self.archived_by_product = false;
+    }
+    self
+  }
+  /// Add new image to the end of the image list
+  /// The first image becomes the primary one
+  pub fn add_image(&mut self, image_id: String) -> &Self {
+    if self.primary_image.is_none() {
+      self.primary_image = Some(image_id.clone());
+    }
+    self.images.push(image_id);
+    self
+  }
+  /// Set primary image
+  pub fn set_primary_image(&mut self, image_id: &str) -> Result<&Self, String> {
+    if !self.images.iter().any(|i| i == image_id) {
+      return Err("A megadott kép nem tartozik a SKU-hoz!".to_string());
+    }
+    self.primary_image = Some(image_id.to_string());
+    Ok(self)
+  }
+  /// Reorder images
+  /// The new order must contain exactly the same image IDs
+  pub fn reorder_images(&mut self, image_ids: Vec<String>) -> Result<&Self, String> {
+    let mut current = self.images.clone();
+    let mut new = image_ids.clone();
+    current.sort();
+    new.sort();
+    if current != new {
+      return Err("Az új sorrendnek pontosan a SKU képeit kell tartalmaznia!".to_string());
+    }
+    self.images = image_ids;
+    Ok(self)
+  }
+  /// Remove image
+  /// If it was the primary image, the first
+  /// remaining image becomes the primary one
+  pub fn remove_image(&mut self, image_id: &str) -> Result<&Self, String> {
+    if !self.images.iter().any(|i| i == image_id) {
+      return Err("A megadott kép nem tartozik a SKU-hoz!".to_string());
+    }
+    self.images.retain(|i| i != image_id);
+    if self.primary_image.as_deref() == Some(image_id) {
+      self.primary_image = self.images.first().cloned();
+    }
+    Ok(self)
+  }
+  /// Add barcode
+  /// Global uniqueness must be checked by the caller
+  pub fn add_barcode(&mut self, barcode: Barcode) -> Result<&Self, String> {
+    if self
+      .all_barcodes()
+      .iter()
+      .any(|b| b.index_key() == barcode.index_key())
+    {
+      return Err("A vonalkód már a SKU-hoz tartozik!".to_string());
+    }
+    self.barcodes.push(barcode);
+    Ok(self)
+  }
+  /// Remove barcode and return it
+  pub fn remove_barcode(&mut self, code: &str) -> Result<Barcode, String> {
+    match self.barcodes.iter().position(|b| b.code == code.trim()) {
+      Some(index) => Ok(self.barcodes.remove(index)),
+      None => Err("A megadott vonalkód nem tartozik a SKU-hoz!".to_string()),
+    }
+  }
+  /// Increase version, called before every stored change
+  pub fn bump_version(&mut self) {
+    self.version += 1;
+  }
   /// Central reset function
   /// This calls all the needed reset sub methods
   /// Call order important!
@@ -227,23 +658,56 @@ impl Sku {
   /// Reset display_name by a parent &Product data
   /// and self data
   pub fn reset_display_name(&mut self) {
-    self.display_name = format!(
-      "{} {}, {}",
-      self.parent_name, self.sub_name, self.display_packaging
-    );
+    self.display_name = self.display_name_with(&self.display_packaging);
+  }
+  /// Display name with the given packaging display
+  /// Attribute values marked as show_in_name are
+  /// added after the sub name
+  fn display_name_with(&self, display_packaging: &str) -> String {
+    let name_attributes = self
+      .attributes
+      .iter()
+      .filter(|a| a.show_in_name)
+      .map(|a| a.value.clone())
+      .collect::<Vec<String>>();
+    match name_attributes.is_empty() {
+      true => format!(
+        "{} {}, {}",
+        self.parent_name, self.sub_name, display_packaging
+      ),
+      false => format!(
+        "{} {} {}, {}",
+        self.parent_name,
+        self.sub_name,
+        name_attributes.join(" "),
+        display_packaging
+      ),
+    }
   }
   /// Reset display_packaging
   /// based on the stored quantity and unit
   pub fn reset_display_packaging(&mut self) {
-    self.display_packaging = fancy_display(&self.quantity, &self.unit);
+    self.display_packaging = self.display_packaging_with(&FormatProfile::default());
+  }
+  /// Packaging display by the given format profile
+  /// Bundles display their components
+  pub fn display_packaging_with(&self, profile: &FormatProfile) -> String {
+    match self.is_bundle() {
+      true => bundle::display_packaging(&self.bundle),
+      false => fancy_display_with(&self.quantity, &self.unit, profile),
+    }
+  }
+  /// Display name and packaging by the given format profile
+  pub fn localize(&mut self, profile: &FormatProfile) -> &Self {
+    self.display_packaging = self.display_packaging_with(profile);
+    self.display_name = self.display_name_with(&self.display_packaging);
+    self
   }
 
+  /// Total content in base units
+  /// 1 if it is not an integer amount
   pub fn get_divisible_amount(&self) -> u32 {
-    match self.quantity {
-      // Only Simple quantity can be divisible
-      Quantity::Simple(q) => return q,
-      _ => return 1,
-    }
+    self.quantity.total().to_u32().unwrap_or(1)
   }
 }
 
@@ -263,6 +727,18 @@ impl Default for Sku {
       perishable: false,
       created_by: 0,
       created_at: Utc::now(),
+      images: Vec::new(),
+      primary_image: None,
+      barcodes: Vec::new(),
+      archived: false,
+      attributes: Vec::new(),
+      tags: Vec::new(),
+      tax_class: None,
+      bundle: Vec::new(),
+      packaging: Vec::new(),
+      divide_rule: DivideRule::default(),
+      version: 1,
+      archived_by_product: false,
     }
   }
 }
@@ -275,5 +751,129 @@ impl VecPackMember for Sku {
 }
 
 impl TryFrom for Sku {
-  type TryFrom = Sku;
+  type TryFrom = SkuOld;
+}
+
+/// Frozen baseline Sku layout
+/// Used to migrate stored SKUs, only this exact layout is loadable.
+/// bincode is not self-describing, so adding fields, even at the end,
+/// breaks loading. Every future Sku layout change needs its own
+/// old struct and migration.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SkuOld {
+  pub sku: u32,
+  pub product_id: u32,
+  pub parent_name: String,
+  pub sub_name: String,
+  pub display_name: String,
+  pub display_packaging: String,
+  pub unit: Unit,
+  pub quantity: Quantity,
+  pub can_divide: bool,
+  pub discontinued: bool,
+  pub perishable: bool,
+  pub created_by: u32,
+  pub created_at: DateTime<Utc>,
+}
+
+impl Default for SkuOld {
+  fn default() -> Self {
+    Self {
+      sku: 0,
+      product_id: 0,
+      parent_name: String::default(),
+      sub_name: String::default(),
+      display_name: String::default(),
+      display_packaging: String::default(),
+      unit: Unit::Milliliter,
+      quantity: Quantity::Simple(0),
+      can_divide: false,
+      discontinued: false,
+      perishable: false,
+      created_by: 0,
+      created_at: Utc::now(),
+    }
+  }
+}
+
+impl From<SkuOld> for Sku {
+  fn from(s: SkuOld) -> Self {
+    Self {
+      sku: s.sku,
+      product_id: s.product_id,
+      parent_name: s.parent_name,
+      sub_name: s.sub_name,
+      display_name: s.display_name,
+      display_packaging: s.display_packaging,
+      unit: s.unit,
+      quantity: s.quantity,
+      can_divide: s.can_divide,
+      discontinued: s.discontinued,
+      perishable: s.perishable,
+      created_by: s.created_by,
+      created_at: s.created_at,
+      images: Vec::new(),
+      primary_image: None,
+      barcodes: Vec::new(),
+      archived: false,
+      attributes: Vec::new(),
+      tags: Vec::new(),
+      tax_class: None,
+      bundle: Vec::new(),
+      packaging: Vec::new(),
+      divide_rule: DivideRule::default(),
+      version: 1,
+      archived_by_product: false,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::attribute::AttributeKind;
+
+  #[test]
+  fn test_sync_attributes() {
+    let mut parent = Product::new(1, "Csiszolópapír".into(), "".into(), Unit::Piece, 1);
+    parent.attributes = vec![
+      AttributeDef::try_new("szemcse", AttributeKind::Integer, true, true).unwrap(),
+      AttributeDef::try_new("méret", AttributeKind::Text, false, false).unwrap(),
+    ];
+    let mut s = Sku::new(1, 1, &parent, "".into(), Quantity::Simple(1), 1);
+    s.set_attributes(
+      &parent.attributes,
+      vec![
+        ("szemcse".into(), "120".into()),
+        ("méret".into(), "A4".into()),
+      ],
+    )
+    .unwrap();
+    // Removed attribute is dropped
+    parent.attributes.truncate(1);
+    s.sync_attributes(&parent.attributes).unwrap();
+    assert_eq!(s.attributes.len(), 1);
+    assert_eq!(s.attributes[0].name, "szemcse");
+    // New required attribute is missing
+    parent
+      .attributes
+      .push(AttributeDef::try_new("szín", AttributeKind::Text, true, false).unwrap());
+    assert!(s.sync_attributes(&parent.attributes).is_err());
+  }
+
+  #[test]
+  fn test_archive_with_product() {
+    let parent = Product::new(1, "Virágföld".into(), "".into(), Unit::Gram, 1);
+    let mut own = Sku::new(1, 1, &parent, "".into(), Quantity::Simple(500), 1);
+    let mut cascaded = own.clone();
+    own.set_archived(true);
+    own.archive_with_product();
+    cascaded.archive_with_product();
+    assert!(own.archived && cascaded.archived);
+    // Only the SKU archived by the product comes back
+    own.restore_with_product();
+    cascaded.restore_with_product();
+    assert!(own.archived);
+    assert!(!cascaded.archived);
+  }
 }
@@ -0,0 +1,3 @@
+// Product service proto definitions
+// Generated from proto/product.proto by build.rs
+tonic::include_proto!("product");
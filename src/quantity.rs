@@ -1,5 +1,7 @@
 use std::fmt::Display;
 
+use crate::convert::{self, MeasureUnit};
+use crate::format::FormatProfile;
 use crate::prelude::*;
 use serde::{Deserialize, Serialize};
 
@@ -9,6 +11,11 @@ pub enum Unit {
   Millimeter,
   Gram,
   Milliliter,
+  SquareMeter,
+  CubicMeter,
+  RunningMeter,
+  Pair,
+  Set,
 }
 
 impl std::fmt::Display for Unit {
@@ -18,6 +25,11 @@ impl std::fmt::Display for Unit {
       Unit::Milliliter => write!(f, "ml"),
       Unit::Gram => write!(f, "g"),
       Unit::Millimeter => write!(f, "mm"),
+      Unit::SquareMeter => write!(f, "m²"),
+      Unit::CubicMeter => write!(f, "m³"),
+      Unit::RunningMeter => write!(f, "fm"),
+      Unit::Pair => write!(f, "pár"),
+      Unit::Set => write!(f, "szett"),
     }
   }
 }
@@ -41,6 +53,18 @@ impl Unit {
       "g" => Unit::Gram,
       "milliliter" => Unit::Milliliter,
       "ml" => Unit::Milliliter,
+      "square_meter" => Unit::SquareMeter,
+      "m2" => Unit::SquareMeter,
+      "m²" => Unit::SquareMeter,
+      "cubic_meter" => Unit::CubicMeter,
+      "m3" => Unit::CubicMeter,
+      "m³" => Unit::CubicMeter,
+      "running_meter" => Unit::RunningMeter,
+      "fm" => Unit::RunningMeter,
+      "pair" => Unit::Pair,
+      "pár" => Unit::Pair,
+      "set" => Unit::Set,
+      "szett" => Unit::Set,
       _ => {
         return Err(ServiceError::bad_request(&format!(
           "Wrong unit format: {}",
@@ -50,60 +74,245 @@ impl Unit {
     };
     Ok(res)
   }
-  pub fn to_display_unit(&self, quantity_display: &QuantityDisplay) -> String {
+  pub fn to_display_unit(&self, quantity_display: &QuantityDisplay) -> MeasureUnit {
     match quantity_display {
       QuantityDisplay::Transformed(_) => {
         match self {
-          // Piece remains piece
-          Unit::Piece => self.to_string(),
           // MM to Meter
-          Unit::Millimeter => "m".to_string(),
+          Unit::Millimeter => MeasureUnit::Meter,
           // Gram to Kg
-          Unit::Gram => "kg".to_string(),
+          Unit::Gram => MeasureUnit::Kilogram,
           // Ml to Liter
-          Unit::Milliliter => "l".to_string(),
+          Unit::Milliliter => MeasureUnit::Liter,
+          // Piece, count units and large units remain as they are
+          _ => MeasureUnit::from(self),
         }
       }
-      QuantityDisplay::Original(_) => format!("{}", &self),
+      QuantityDisplay::Original(_) => MeasureUnit::from(self),
     }
   }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub enum Quantity {
-  Simple(u32),
-  Complex(u32, u32),
-  Float(f32),
+/// Maximum number of fraction digits of a Decimal
+pub const DECIMAL_MAX_SCALE: u32 = 6;
+
+/// Exact fixed-point decimal number
+/// The represented number is value / 10^scale
+/// Always stored in normalized form, without trailing fraction zeros,
+/// so derived equality is exact
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Decimal {
+  value: u64,
+  scale: u32,
 }
 
-impl PartialEq for Quantity {
-  fn eq(&self, other: &Self) -> bool {
-    match self {
-      Quantity::Float(q) => match other {
-        Quantity::Float(q2) => q == q2,
-        Quantity::Simple(_) => false,
-        Quantity::Complex(_, _) => false,
-      },
-      Quantity::Simple(q) => match other {
-        Quantity::Float(_) => false,
-        Quantity::Simple(q2) => q == q2,
-        Quantity::Complex(_, _) => false,
-      },
-      Quantity::Complex(m, q) => match other {
-        Quantity::Float(_) => false,
-        Quantity::Simple(_) => false,
-        Quantity::Complex(m2, q2) => m == m2 && q == q2,
-      },
+impl Decimal {
+  /// Create new normalized decimal
+  pub fn new(value: u64, scale: u32) -> Self {
+    let mut res = Self { value, scale };
+    while res.scale > 0 && res.value.is_multiple_of(10) {
+      res.value /= 10;
+      res.scale -= 1;
+    }
+    res
+  }
+  /// Parse decimal from its string representation, e.g. 2.25
+  pub fn try_from_str(s: &str) -> ServiceResult<Decimal> {
+    let s = s.trim();
+    let (integer, fraction) = match s.split_once('.') {
+      Some((i, f)) => (i, f),
+      None => (s, ""),
+    };
+    let is_digits = |p: &str| p.chars().all(|c| c.is_ascii_digit());
+    if integer.is_empty() || !is_digits(integer) || !is_digits(fraction) || s.ends_with('.') {
+      return Err(ServiceError::bad_request(&format!(
+        "A megadott szám hibás tizedes tört: {}",
+        s
+      )));
+    }
+    if fraction.len() as u32 > DECIMAL_MAX_SCALE {
+      return Err(ServiceError::bad_request(&format!(
+        "A tizedes tört legfeljebb {} tizedesjegyből állhat: {}",
+        DECIMAL_MAX_SCALE, s
+      )));
+    }
+    let value = format!("{}{}", integer, fraction)
+      .parse::<u64>()
+      .map_err(|_| ServiceError::bad_request(&format!("A megadott szám túl nagy: {}", s)))?;
+    Ok(Decimal::new(value, fraction.len() as u32))
+  }
+  /// Integer value if it has no fraction part and fits into u32
+  pub fn to_u32(self) -> Option<u32> {
+    match self.scale == 0 && self.value <= u32::MAX as u64 {
+      true => Some(self.value as u32),
+      false => None,
+    }
+  }
+  /// Multiply by 10^exp
+  /// Returns None on overflow
+  pub fn shift_left(&self, exp: u32) -> Option<Decimal> {
+    match self.scale >= exp {
+      true => Some(Decimal::new(self.value, self.scale - exp)),
+      false => self
+        .value
+        .checked_mul(10u64.checked_pow(exp - self.scale)?)
+        .map(|v| Decimal::new(v, 0)),
+    }
+  }
+  /// Divide by 10^exp
+  pub fn shift_right(&self, exp: u32) -> Decimal {
+    Decimal::new(self.value, self.scale + exp)
+  }
+  /// Multiply by an integer
+  /// Returns None on overflow
+  pub fn checked_mul(&self, m: u64) -> Option<Decimal> {
+    self
+      .value
+      .checked_mul(m)
+      .map(|v| Decimal::new(v, self.scale))
+  }
+  /// Divide n by self, rounded half up to integer
+  /// Returns None if self is zero or on overflow
+  pub fn divide(&self, n: u64) -> Option<u64> {
+    if self.value == 0 {
+      return None;
     }
+    let numerator = (n as u128).checked_mul(10u128.checked_pow(self.scale)?)?;
+    let value = self.value as u128;
+    let res = (numerator * 2 + value) / (value * 2);
+    match res <= u64::MAX as u128 {
+      true => Some(res as u64),
+      false => None,
+    }
+  }
+  /// Convert a legacy float value
+  /// Uses the shortest representation that round-trips to the same f32,
+  /// so 0.05 stays 0.05 instead of its binary approximation.
+  /// Longer fractions are rounded to DECIMAL_MAX_SCALE digits.
+  /// Negative, NaN, infinite and too large values are rejected
+  pub fn try_from_f32(f: f32) -> ServiceResult<Decimal> {
+    if !f.is_finite() || f < 0.0 {
+      return Err(ServiceError::bad_request(&format!(
+        "A mennyiség nem alakítható tizedes törtté: {}",
+        f
+      )));
+    }
+    if f == 0.0 {
+      return Ok(Decimal::default());
+    }
+    let s = f.to_string();
+    match s.split_once('.') {
+      Some((_, fraction)) if fraction.len() as u32 > DECIMAL_MAX_SCALE => {
+        Decimal::try_from_str(&format!("{:.*}", DECIMAL_MAX_SCALE as usize, f))
+      }
+      _ => Decimal::try_from_str(&s),
+    }
+  }
+  /// Convert a legacy float value clamped into the Decimal range
+  /// Negative and NaN values become 0, too large ones the maximum
+  pub fn from_f32_clamped(f: f32) -> Decimal {
+    match Decimal::try_from_f32(f) {
+      Ok(d) => d,
+      Err(_) if f > 0.0 => Decimal::new(u64::MAX, 0),
+      Err(_) => Decimal::default(),
+    }
+  }
+}
+
+impl Decimal {
+  /// Unscaled values aligned to the same scale
+  fn aligned(&self, other: &Decimal) -> (u128, u128, u32) {
+    let scale = self.scale.max(other.scale);
+    (
+      self.value as u128 * 10u128.pow(scale - self.scale),
+      other.value as u128 * 10u128.pow(scale - other.scale),
+      scale,
+    )
+  }
+  /// Add two decimals
+  /// Returns None on overflow
+  pub fn checked_add(&self, other: &Decimal) -> Option<Decimal> {
+    let (a, b, scale) = self.aligned(other);
+    let value = a.checked_add(b)?;
+    match value <= u64::MAX as u128 {
+      true => Some(Decimal::new(value as u64, scale)),
+      false => None,
+    }
+  }
+  /// Subtract other from self
+  /// Returns None if the result would be negative
+  pub fn checked_sub(&self, other: &Decimal) -> Option<Decimal> {
+    let (a, b, scale) = self.aligned(other);
+    a.checked_sub(b).map(|v| Decimal::new(v as u64, scale))
+  }
+  /// Check if self is an integer multiple of other
+  /// Nothing is a multiple of zero, except zero
+  pub fn is_multiple_of(&self, other: &Decimal) -> bool {
+    let (a, b, _) = self.aligned(other);
+    a.is_multiple_of(b)
+  }
+}
+
+impl PartialOrd for Decimal {
+  fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for Decimal {
+  fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+    let (a, b, _) = self.aligned(other);
+    a.cmp(&b)
   }
 }
 
+impl Decimal {
+  /// Product of two u32 integers, it always fits
+  pub fn from_product(a: u32, b: u32) -> Decimal {
+    Decimal::new(a as u64 * b as u64, 0)
+  }
+}
+
+impl From<u32> for Decimal {
+  fn from(value: u32) -> Self {
+    Decimal::new(value as u64, 0)
+  }
+}
+
+impl std::fmt::Display for Decimal {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    if self.scale == 0 {
+      return write!(f, "{}", self.value);
+    }
+    let divisor = 10u64.pow(self.scale);
+    write!(
+      f,
+      "{}.{:0width$}",
+      self.value / divisor,
+      self.value % divisor,
+      width = self.scale as usize
+    )
+  }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Quantity {
+  Simple(u32),
+  Complex(u32, u32),
+  /// Legacy inexact quantity
+  /// Not created anymore, stored values are migrated to Decimal
+  Float(f32),
+  /// Exact decimal quantity
+  Decimal(Decimal),
+}
+
 impl std::fmt::Display for Quantity {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     match &self {
       Quantity::Float(quantity) => write!(f, "{:.1}", quantity),
       Quantity::Simple(quantity) => write!(f, "{}", quantity),
       Quantity::Complex(multiplier, quantity) => write!(f, "{}x{}", multiplier, quantity),
+      Quantity::Decimal(quantity) => write!(f, "{}", quantity),
     }
   }
 }
@@ -115,54 +324,165 @@ impl Into<String> for Quantity {
 }
 
 impl Quantity {
+  /// Parse quantity without unit suffix, e.g. 5, 3x500 or 2,5
+  /// Service code parses by the product unit, see try_from_str_in
+  #[cfg(test)]
   pub fn try_from_str(s: &str) -> ServiceResult<Quantity> {
-    let s = s.trim();
-
-    let u32parser = |input: &str| -> ServiceResult<u32> {
-      match input.parse::<u32>() {
-        Ok(res) => Ok(res),
-        Err(_) => Err(ServiceError::bad_request(
-          "A megadott mennyiség csak pozitív egész számból állhat",
-        )),
+    Quantity::parse(s, None)
+  }
+  /// Parse quantity of a product with the given unit
+  /// An optional unit suffix is converted to the product unit,
+  /// e.g. 1.5 kg becomes 1500 for a gram product
+  pub fn try_from_str_in(s: &str, unit: &Unit) -> ServiceResult<Quantity> {
+    Quantity::parse(s, Some(unit))
+  }
+  /// Accepts decimal comma, whitespaces, x or × as multiplier sign
+  /// and an optional unit suffix, e.g. 2 × 250ml
+  fn parse(s: &str, unit: Option<&Unit>) -> ServiceResult<Quantity> {
+    let input = s.trim();
+    if input.is_empty() {
+      return Err(ServiceError::bad_request("A mennyiség nem lehet üres!"));
+    }
+    let parts: Vec<&str> = input.split(['x', 'X', '×']).collect();
+    let (multiplier, amount) = match parts.as_slice() {
+      [amount] => (None, *amount),
+      [multiplier, amount] => (Some(multiplier.trim()), *amount),
+      _ => {
+        return Err(ServiceError::bad_request(&format!(
+          "A komplex mennyiség csak 2 részből állhat, pl.: 3x500. Hibás érték: {}",
+          input
+        )))
       }
     };
-
-    let f32parser = |input: &str| -> ServiceResult<f32> {
-      match input.parse::<f32>() {
-        Ok(res) => Ok(res),
-        Err(_) => Err(ServiceError::bad_request(
-          "A megadott szám hibás tizedes tört",
-        )),
-      }
+    // Parse multiplier
+    let multiplier = match multiplier {
+      Some(m) => Some(m.parse::<u32>().map_err(|_| {
+        ServiceError::bad_request(&format!(
+          "Hibás szorzó: '{}'. A szorzó csak pozitív egész szám lehet!",
+          m
+        ))
+      })?),
+      None => None,
     };
+    // Split amount and unit suffix
+    let amount = amount.trim();
+    let number_end = amount
+      .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ','))
+      .unwrap_or(amount.len());
+    let (number, suffix) = amount.split_at(number_end);
+    let (number, suffix) = (number.trim(), suffix.trim());
+    if number.is_empty() {
+      return Err(ServiceError::bad_request(&format!(
+        "Hiányzó mennyiség: '{}'",
+        amount
+      )));
+    }
+    // Parse amount with decimal point or comma
+    let mut value = Decimal::try_from_str(&number.replace(',', ".")).map_err(|_| {
+      ServiceError::bad_request(&format!(
+        "Hibás mennyiség: '{}'. Pl.: 500, 1.5 vagy 1,5",
+        number
+      ))
+    })?;
+    // Convert amount from the suffix unit to the product unit
+    if !suffix.is_empty() {
+      let unit = unit.ok_or_else(|| {
+        ServiceError::bad_request(&format!(
+          "A mennyiség nem tartalmazhat mértékegységet: '{}'",
+          suffix
+        ))
+      })?;
+      let from = MeasureUnit::try_from_str(suffix).map_err(|_| {
+        ServiceError::bad_request(&format!("Ismeretlen mértékegység: '{}'", suffix))
+      })?;
+      value = convert::convert(value, from, MeasureUnit::from(unit)).map_err(|_| {
+        ServiceError::bad_request(&format!(
+          "A(z) '{}' mértékegység nem váltható át a termék egységére ({})",
+          suffix, unit
+        ))
+      })?;
+    }
+    match multiplier {
+      Some(m) => match value.to_u32() {
+        Some(q) => Ok(Quantity::Complex(m, q)),
+        None => Err(ServiceError::bad_request(&format!(
+          "A komplex mennyiség csak egész számokból állhat. Hibás érték: '{}'",
+          amount
+        ))),
+      },
+      None => match value.to_u32() {
+        Some(q) => Ok(Quantity::Simple(q)),
+        None => Ok(Quantity::Decimal(value)),
+      },
+    }
+  }
+  /// Total content in the base unit, e.g. 3x500 is 1500
+  pub fn total(&self) -> Decimal {
+    match self {
+      Quantity::Simple(q) => Decimal::from(*q),
+      Quantity::Complex(m, q) => Decimal::from_product(*q, *m),
+      Quantity::Decimal(d) => *d,
+      // Legacy values are migrated on startup,
+      // the ones that cannot be migrated are clamped
+      Quantity::Float(f) => Decimal::from_f32_clamped(*f),
+    }
+  }
+  /// Total ordering by content
+  /// Quantities with the same content are ordered as
+  /// Simple, Decimal, then Complex by multiplier
+  pub fn cmp_content(&self, other: &Quantity) -> std::cmp::Ordering {
+    let rank = |q: &Quantity| match q {
+      Quantity::Simple(_) => (0, 0),
+      Quantity::Float(_) | Quantity::Decimal(_) => (1, 0),
+      Quantity::Complex(m, _) => (2, *m),
+    };
+    self
+      .total()
+      .cmp(&other.total())
+      .then_with(|| rank(self).cmp(&rank(other)))
+  }
+  /// Quantity from a total content
+  /// Simple if it is a u32 integer, Decimal otherwise
+  pub fn from_total(total: Decimal) -> Quantity {
+    match total.to_u32() {
+      Some(q) => Quantity::Simple(q),
+      None => Quantity::Decimal(total),
+    }
+  }
+  /// Migrate legacy Float quantity to an exact Decimal one
+  /// Other variants are returned unchanged
+  pub fn migrate(&self) -> ServiceResult<Quantity> {
+    match self {
+      Quantity::Float(f) => Ok(Quantity::Decimal(Decimal::try_from_f32(*f)?)),
+      _ => Ok(self.clone()),
+    }
+  }
+}
 
-    match s.contains("x") {
-      true => {
-        let parts: Vec<&str> = s.split("x").collect();
-        if parts.len() == 2 {
-          let multiplier = if let Some(_multiplier) = parts.get(0) {
-            u32parser(_multiplier)?
-          } else {
-            return Err(ServiceError::internal_error("This should never happen"));
-          };
-          let quantity = if let Some(_quantity) = parts.get(1) {
-            u32parser(_quantity)?
-          } else {
-            return Err(ServiceError::internal_error("This should never happen"));
-          };
-          return Ok(Quantity::Complex(multiplier, quantity));
-        } else {
-          return Err(ServiceError::bad_request(
-            "A komplex mennyiség csak 2 részből állhat. eg.: 3x5",
-          ));
-        }
-      }
-      false => match s.contains(".") {
-        // If its a f32
-        true => return Ok(Quantity::Float(f32parser(s)?)),
-        // If its an u32
-        false => return Ok(Quantity::Simple(u32parser(s)?)),
+impl Quantity {
+  /// Sum of two quantities of the same unit
+  /// Result is the total content of both
+  /// Returns None on overflow
+  pub fn checked_add(&self, other: &Quantity) -> Option<Quantity> {
+    self
+      .total()
+      .checked_add(&other.total())
+      .map(Quantity::from_total)
+  }
+  /// Scale quantity of a unit by n
+  /// Complex quantities keep their pack size
+  /// Returns None on overflow
+  pub fn checked_mul(&self, n: u32) -> Option<Quantity> {
+    match self {
+      Quantity::Simple(q) => match q.checked_mul(n) {
+        Some(res) => Some(Quantity::Simple(res)),
+        None => Some(Quantity::from_total(Decimal::from_product(*q, n))),
+      },
+      Quantity::Complex(m, q) => match m.checked_mul(n) {
+        Some(res) => Some(Quantity::Complex(res, *q)),
+        None => self.total().checked_mul(n as u64).map(Quantity::from_total),
       },
+      _ => self.total().checked_mul(n as u64).map(Quantity::from_total),
     }
   }
 }
@@ -181,14 +501,24 @@ impl<'a> Display for QuantityDisplay<'a> {
   }
 }
 
+impl<'a> QuantityDisplay<'a> {
+  fn quantity(&self) -> &Quantity {
+    match self {
+      QuantityDisplay::Transformed(q) => q,
+      QuantityDisplay::Original(q) => q,
+    }
+  }
+}
+
 /// Convert a quantity and a unit to a nice looking
-/// easier to look format
-pub fn fancy_display(quantity: &Quantity, unit: &Unit) -> String {
+/// format using the given format profile
+pub fn fancy_display_with(quantity: &Quantity, unit: &Unit, profile: &FormatProfile) -> String {
   // Helper to decide wether transform quantity or not
   let can_transform = |u: u32| (u >= 1000) && (u % 1000 == 0);
   // Transform quantity
   let transformed = |q: &Quantity| match q {
     Quantity::Float(_q) => QuantityDisplay::Original(quantity),
+    Quantity::Decimal(_q) => QuantityDisplay::Original(quantity),
     Quantity::Simple(_q) => match can_transform(*_q) {
       true => QuantityDisplay::Transformed(Quantity::Simple(_q / 1000)),
       false => QuantityDisplay::Original(quantity),
@@ -202,12 +532,22 @@ pub fn fancy_display(quantity: &Quantity, unit: &Unit) -> String {
   let quantity_transformed = transformed(quantity);
   // Create display string
   match unit {
-    // When we have a Piece, we do not transform anything
-    Unit::Piece => format!("{} {}", quantity, unit),
+    // When we have a Piece, we do not transform anything,
+    // nor count units and units already large enough
+    Unit::Piece
+    | Unit::Pair
+    | Unit::Set
+    | Unit::SquareMeter
+    | Unit::CubicMeter
+    | Unit::RunningMeter => format!(
+      "{} {}",
+      profile.quantity(quantity),
+      profile.label(MeasureUnit::from(unit))
+    ),
     _ => format!(
       "{} {}",
-      &quantity_transformed,
-      unit.to_display_unit(&quantity_transformed)
+      profile.quantity(quantity_transformed.quantity()),
+      profile.label(unit.to_display_unit(&quantity_transformed))
     ),
   }
 }
@@ -216,6 +556,11 @@ pub fn fancy_display(quantity: &Quantity, unit: &Unit) -> String {
 mod tests {
   use super::*;
 
+  /// Display by the default profile of the stored display strings
+  fn fancy_display(quantity: &Quantity, unit: &Unit) -> String {
+    fancy_display_with(quantity, unit, &FormatProfile::default())
+  }
+
   #[test]
   fn test_quantity_convert() {
     assert_eq!(Quantity::try_from_str("5").unwrap(), Quantity::Simple(5));
@@ -230,7 +575,151 @@ mod tests {
     assert_eq!(Quantity::try_from_str("1x").is_err(), true);
     assert_eq!(Quantity::try_from_str("1x3e").is_err(), true);
     assert_eq!(Quantity::try_from_str("2.5").is_ok(), true);
-    assert_eq!(Quantity::try_from_str("2.5").unwrap(), Quantity::Float(2.5));
+    assert_eq!(
+      Quantity::try_from_str("2.5").unwrap(),
+      Quantity::Decimal(Decimal::new(25, 1))
+    );
+    assert_eq!(
+      Quantity::try_from_str("2.50").unwrap(),
+      Quantity::Decimal(Decimal::new(25, 1))
+    );
+    assert!(Quantity::try_from_str("2.").is_err());
+    assert!(Quantity::try_from_str(".5").is_err());
+    assert!(Quantity::try_from_str("1.1234567").is_err());
+  }
+
+  #[test]
+  fn test_quantity_parse_in() {
+    let parse = |s: &str, u: Unit| Quantity::try_from_str_in(s, &u).unwrap();
+    assert_eq!(
+      parse("1,5", Unit::Gram),
+      Quantity::Decimal(Decimal::new(15, 1))
+    );
+    assert_eq!(parse(" 3 x 500 ", Unit::Gram), Quantity::Complex(3, 500));
+    assert_eq!(parse("500 g", Unit::Gram), Quantity::Simple(500));
+    assert_eq!(parse("1.5 kg", Unit::Gram), Quantity::Simple(1500));
+    assert_eq!(parse("1,5kg", Unit::Gram), Quantity::Simple(1500));
+    assert_eq!(
+      parse("2×250ml", Unit::Milliliter),
+      Quantity::Complex(2, 250)
+    );
+    assert_eq!(
+      parse("3X0,5 l", Unit::Milliliter),
+      Quantity::Complex(3, 500)
+    );
+    assert_eq!(parse("2 m", Unit::Millimeter), Quantity::Simple(2000));
+    assert_eq!(parse("25 m2", Unit::SquareMeter), Quantity::Simple(25));
+    assert_eq!(parse("4 db", Unit::Piece), Quantity::Simple(4));
+    assert_eq!(parse("1 pár", Unit::Pair), Quantity::Simple(1));
+    assert_eq!(
+      parse("250 g", Unit::Gram),
+      Quantity::try_from_str("250").unwrap()
+    );
+    // Wrong parts
+    let err = |s: &str, u: Unit| Quantity::try_from_str_in(s, &u).unwrap_err().to_string();
+    assert!(err("1.5 kg", Unit::Milliliter).contains("'kg'"));
+    assert!(err("1.5 lbs", Unit::Gram).contains("'lbs'"));
+    assert!(err("a x 500", Unit::Gram).contains("'a'"));
+    assert!(err("3 x 1.5.0", Unit::Gram).contains("'1.5.0'"));
+    assert!(err("3 x 0.5", Unit::Gram).contains("egész"));
+    assert!(err("kg", Unit::Gram).contains("Hiányzó"));
+    assert!(err("", Unit::Gram).contains("üres"));
+    // Unit suffix needs a product unit
+    assert!(Quantity::try_from_str("500 g").is_err());
+  }
+
+  #[test]
+  fn test_quantity_arithmetic() {
+    let d = |s: &str| Decimal::try_from_str(s).unwrap();
+    assert_eq!(Quantity::Complex(3, 500).total(), d("1500"));
+    assert_eq!(Quantity::Simple(7).total(), d("7"));
+    assert_eq!(Quantity::Decimal(d("2.25")).total(), d("2.25"));
+    assert_eq!(Quantity::Float(0.5).total(), d("0.5"));
+    let add = |a: Quantity, b: Quantity| a.checked_add(&b).unwrap();
+    assert_eq!(
+      add(Quantity::Complex(3, 500), Quantity::Simple(250)),
+      Quantity::Simple(1750)
+    );
+    assert_eq!(
+      add(Quantity::Simple(1), Quantity::Decimal(d("0.25"))),
+      Quantity::Decimal(d("1.25"))
+    );
+    let mul = |q: Quantity, n: u32| q.checked_mul(n).unwrap();
+    assert_eq!(mul(Quantity::Simple(500), 3), Quantity::Simple(1500));
+    assert_eq!(mul(Quantity::Complex(3, 500), 2), Quantity::Complex(6, 500));
+    assert_eq!(
+      mul(Quantity::Decimal(d("0.5")), 3),
+      Quantity::Decimal(d("1.5"))
+    );
+    assert_eq!(mul(Quantity::Decimal(d("0.5")), 4), Quantity::Simple(2));
+    assert_eq!(
+      mul(Quantity::Simple(u32::MAX), 2),
+      Quantity::Decimal(d("8589934590"))
+    );
+    // Overflow is reported instead of panicking
+    let max = Quantity::Decimal(Decimal::new(u64::MAX, 0));
+    assert_eq!(max.checked_mul(2), None);
+    assert_eq!(max.checked_add(&Quantity::Simple(1)), None);
+    assert_eq!(
+      Quantity::Complex(u32::MAX, u32::MAX).total(),
+      Decimal::from_product(u32::MAX, u32::MAX)
+    );
+    assert_eq!(d("0.5").checked_add(&d("0.25")), Some(d("0.75")));
+    assert!(d("0.5") < d("0.75"));
+    assert!(d("10") > d("9.999"));
+  }
+
+  #[test]
+  fn test_quantity_ordering() {
+    let mut quantities = vec![
+      Quantity::Complex(3, 500),
+      Quantity::Simple(1500),
+      Quantity::Decimal(Decimal::new(5, 1)),
+      Quantity::Simple(2000),
+      Quantity::Complex(2, 750),
+      Quantity::Simple(1),
+    ];
+    quantities.sort_by(|a, b| a.cmp_content(b));
+    assert_eq!(
+      quantities,
+      vec![
+        Quantity::Decimal(Decimal::new(5, 1)),
+        Quantity::Simple(1),
+        Quantity::Simple(1500),
+        Quantity::Complex(2, 750),
+        Quantity::Complex(3, 500),
+        Quantity::Simple(2000),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_decimal() {
+    assert_eq!(Decimal::try_from_str("0.05").unwrap().to_string(), "0.05");
+    assert_eq!(Decimal::try_from_str("2.25").unwrap().to_string(), "2.25");
+    assert_eq!(
+      Decimal::try_from_str("10.050").unwrap().to_string(),
+      "10.05"
+    );
+    assert_eq!(Decimal::try_from_str("3.0").unwrap().to_string(), "3");
+    assert_eq!(Decimal::new(1005, 2), Decimal::new(10050, 3));
+    assert_eq!(Decimal::try_from_f32(0.05).unwrap(), Decimal::new(5, 2));
+    assert_eq!(Decimal::try_from_f32(2.25).unwrap(), Decimal::new(225, 2));
+    assert_eq!(
+      Quantity::Float(2.25).migrate().unwrap(),
+      Quantity::Decimal(Decimal::new(225, 2))
+    );
+    assert_eq!(Quantity::Simple(5).migrate().unwrap(), Quantity::Simple(5));
+    // Long fractions are rounded
+    assert_eq!(
+      Decimal::try_from_f32(0.1234567).unwrap(),
+      Decimal::new(123457, 6)
+    );
+    assert!(Decimal::try_from_f32(-1.5).is_err());
+    assert!(Decimal::try_from_f32(f32::NAN).is_err());
+    assert!(Decimal::try_from_f32(1e20).is_err());
+    assert_eq!(Quantity::Float(-1.5).total(), Decimal::default());
+    assert_eq!(Quantity::Float(1e20).total(), Decimal::new(u64::MAX, 0));
   }
 
   #[test]
@@ -250,6 +739,69 @@ mod tests {
     assert_eq!(Unit::try_from_str(" g ").is_ok(), true);
     assert_eq!(Unit::try_from_str(" db ").is_ok(), true);
     assert_eq!(Unit::try_from_str("     piece ").is_ok(), true);
+    assert_eq!(Unit::try_from_str("m2").unwrap(), Unit::SquareMeter);
+    assert_eq!(Unit::try_from_str("m²").unwrap(), Unit::SquareMeter);
+    assert_eq!(Unit::try_from_str("m3").unwrap(), Unit::CubicMeter);
+    assert_eq!(Unit::try_from_str("m³").unwrap(), Unit::CubicMeter);
+    assert_eq!(Unit::try_from_str("fm").unwrap(), Unit::RunningMeter);
+    assert_eq!(Unit::try_from_str("pár").unwrap(), Unit::Pair);
+    assert_eq!(Unit::try_from_str("szett").unwrap(), Unit::Set);
+    // Display must parse back
+    for unit in [
+      Unit::SquareMeter,
+      Unit::CubicMeter,
+      Unit::RunningMeter,
+      Unit::Pair,
+      Unit::Set,
+    ] {
+      assert_eq!(Unit::try_from_str(&unit.to_string()).unwrap(), unit);
+    }
+  }
+
+  #[test]
+  fn test_fancy_display_with() {
+    let hu = FormatProfile::hungarian();
+    let en = FormatProfile::english();
+    assert_eq!(
+      fancy_display_with(&Quantity::Simple(11000), &Unit::Piece, &hu),
+      "11 000 db"
+    );
+    assert_eq!(
+      fancy_display_with(&Quantity::Simple(11000), &Unit::Piece, &en),
+      "11,000 pcs"
+    );
+    assert_eq!(
+      fancy_display_with(&Quantity::Simple(16000), &Unit::Gram, &hu),
+      "16 kg"
+    );
+    assert_eq!(
+      fancy_display_with(&Quantity::Simple(16500), &Unit::Gram, &hu),
+      "16 500 g"
+    );
+    assert_eq!(
+      fancy_display_with(
+        &Quantity::Decimal(Decimal::new(15, 1)),
+        &Unit::Milliliter,
+        &hu
+      ),
+      "1,5 ml"
+    );
+    assert_eq!(
+      fancy_display_with(&Quantity::Complex(12, 1500), &Unit::Millimeter, &en),
+      "12x1,500 mm"
+    );
+    assert_eq!(
+      fancy_display_with(&Quantity::Simple(2), &Unit::Pair, &en),
+      "2 pair"
+    );
+    assert_eq!(
+      fancy_display_with(
+        &Quantity::Simple(11000),
+        &Unit::Piece,
+        &FormatProfile::default()
+      ),
+      fancy_display(&Quantity::Simple(11000), &Unit::Piece)
+    );
   }
 
   #[test]
@@ -260,6 +812,37 @@ mod tests {
       fancy_display(&Quantity::Float(1002.5), &Unit::Piece),
       "1002.5 db"
     );
+    // Test Decimal
+    assert_eq!(
+      fancy_display(&Quantity::Decimal(Decimal::new(5, 2)), &Unit::Gram),
+      "0.05 g"
+    );
+    assert_eq!(
+      fancy_display(&Quantity::Decimal(Decimal::new(225, 2)), &Unit::Piece),
+      "2.25 db"
+    );
+    // Test area, volume, length and count units
+    assert_eq!(
+      fancy_display(&Quantity::Simple(25), &Unit::SquareMeter),
+      "25 m²"
+    );
+    assert_eq!(
+      fancy_display(&Quantity::Simple(2000), &Unit::SquareMeter),
+      "2000 m²"
+    );
+    assert_eq!(
+      fancy_display(&Quantity::Decimal(Decimal::new(5, 1)), &Unit::CubicMeter),
+      "0.5 m³"
+    );
+    assert_eq!(
+      fancy_display(&Quantity::Complex(2, 50), &Unit::RunningMeter),
+      "2x50 fm"
+    );
+    assert_eq!(fancy_display(&Quantity::Simple(1), &Unit::Pair), "1 pár");
+    assert_eq!(
+      fancy_display(&Quantity::Simple(1000), &Unit::Set),
+      "1000 szett"
+    );
     // Test piece transform
     assert_eq!(fancy_display(&Quantity::Simple(1), &Unit::Piece), "1 db");
     assert_eq!(
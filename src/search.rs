@@ -0,0 +1,295 @@
+// Copyright (C) 2020 Peter Mezei
+//
+// This file is part of Gardenzilla.
+//
+// Gardenzilla is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// Gardenzilla is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Gardenzilla.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::prelude::*;
+use crate::product::{Product, Sku};
+
+/// Maximum number of typos a query token may contain
+pub const MAX_TYPOS: u32 = 2;
+
+/// Field weight of product names
+pub const WEIGHT_NAME: u32 = 4;
+/// Field weight of SKU sub names
+pub const WEIGHT_SUB_NAME: u32 = 2;
+/// Field weight of product descriptions
+pub const WEIGHT_DESCRIPTION: u32 = 1;
+
+/// Search hit with its relevance score
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hit {
+  pub id: u32,
+  pub score: u32,
+}
+
+/// Normalize text for searching
+/// Lowercase, Hungarian (and a few other) accents are removed,
+/// everything except letters and numbers becomes a space
+pub fn normalize(s: &str) -> String {
+  s.chars()
+    .flat_map(|c| c.to_lowercase())
+    .map(|c| match c {
+      'á' | 'à' | 'â' | 'ä' => 'a',
+      'é' | 'è' | 'ê' | 'ë' => 'e',
+      'í' | 'ì' | 'î' | 'ï' => 'i',
+      // ô and õ are common legacy encodings of ő
+      'ó' | 'ò' | 'ô' | 'õ' | 'ö' | 'ő' => 'o',
+      // û is a common legacy encoding of ű
+      'ú' | 'ù' | 'û' | 'ü' | 'ű' => 'u',
+      c if c.is_alphanumeric() => c,
+      _ => ' ',
+    })
+    .collect()
+}
+
+/// Split text into normalized tokens
+pub fn tokenize(s: &str) -> Vec<String> {
+  normalize(s)
+    .split_whitespace()
+    .map(|t| t.to_string())
+    .collect()
+}
+
+/// Check requested typo tolerance
+pub fn check_typos(max_typos: u32) -> ServiceResult<u32> {
+  match max_typos <= MAX_TYPOS {
+    true => Ok(max_typos),
+    false => Err(ServiceError::bad_request(&format!(
+      "A megengedett elütések száma legfeljebb {} lehet!",
+      MAX_TYPOS
+    ))),
+  }
+}
+
+/// Edit distance of two strings
+/// Insertion, deletion, substitution and swapping
+/// two neighbouring characters count as one edit
+pub fn edit_distance(a: &str, b: &str) -> usize {
+  let a = a.chars().collect::<Vec<char>>();
+  let b = b.chars().collect::<Vec<char>>();
+  // d[i][j] is the distance of a[..i] and b[..j]
+  let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+  for (i, row) in d.iter_mut().enumerate() {
+    row[0] = i;
+  }
+  for (j, cell) in d[0].iter_mut().enumerate() {
+    *cell = j;
+  }
+  for i in 1..=a.len() {
+    for j in 1..=b.len() {
+      let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+      d[i][j] = (d[i - 1][j] + 1)
+        .min(d[i][j - 1] + 1)
+        .min(d[i - 1][j - 1] + cost);
+      if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+        d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+      }
+    }
+  }
+  d[a.len()][b.len()]
+}
+
+/// Typos allowed in a query token
+/// Short tokens must match exactly, otherwise
+/// almost everything would match them
+fn allowed_typos(query: &str, max_typos: u32) -> usize {
+  match query.chars().count() {
+    0..=3 => 0,
+    4..=7 => max_typos.min(1) as usize,
+    _ => max_typos as usize,
+  }
+}
+
+/// Match score of a query token against a text token
+/// exact: 12, prefix: 9, inner part: 6,
+/// fuzzy: 3 minus the typos, no match: 0
+fn token_score(query: &str, token: &str, max_typos: u32) -> u32 {
+  if token == query {
+    return 12;
+  }
+  if token.starts_with(query) {
+    return 9;
+  }
+  if token.contains(query) {
+    return 6;
+  }
+  let allowed = allowed_typos(query, max_typos);
+  if allowed == 0 {
+    return 0;
+  }
+  // Compare to the whole token and to its prefix
+  // of the query length, so partial words match too
+  let prefix = token
+    .chars()
+    .take(query.chars().count())
+    .collect::<String>();
+  let typos = edit_distance(query, token).min(edit_distance(query, &prefix));
+  match typos <= allowed {
+    true => 3 - typos as u32,
+    false => 0,
+  }
+}
+
+/// Relevance of a document made of (text, weight) fields
+/// Every query token must match somewhere, otherwise None
+/// Empty query matches everything with zero score
+pub fn score(query: &[String], fields: &[(&str, u32)], max_typos: u32) -> Option<u32> {
+  let fields = fields
+    .iter()
+    .map(|(text, weight)| (tokenize(text), *weight))
+    .collect::<Vec<(Vec<String>, u32)>>();
+  query
+    .iter()
+    .map(|q| {
+      fields
+        .iter()
+        .flat_map(|(tokens, weight)| {
+          tokens
+            .iter()
+            .map(move |t| token_score(q, t, max_typos) * weight)
+        })
+        .max()
+        .filter(|s| *s > 0)
+    })
+    .sum()
+}
+
+/// Rank documents by the query, most relevant first
+/// Query tokens may contain max_typos typos, 0 means exact matching
+/// Documents with equal score keep their original order
+pub fn rank<'a, I>(query: &str, docs: I, max_typos: u32) -> Vec<Hit>
+where
+  I: Iterator<Item = (u32, Vec<(&'a str, u32)>)>,
+{
+  let query = tokenize(query);
+  let mut res = docs
+    .filter_map(|(id, fields)| score(&query, &fields, max_typos).map(|score| Hit { id, score }))
+    .collect::<Vec<Hit>>();
+  res.sort_by_key(|h| std::cmp::Reverse(h.score));
+  res
+}
+
+/// Searchable fields of a product
+pub fn product_fields(p: &Product) -> Vec<(&str, u32)> {
+  vec![(&p.name, WEIGHT_NAME), (&p.description, WEIGHT_DESCRIPTION)]
+}
+
+/// Searchable fields of a SKU
+/// Description is inherited from the related product
+pub fn sku_fields<'a>(s: &'a Sku, description: &'a str) -> Vec<(&'a str, u32)> {
+  vec![
+    (&s.parent_name, WEIGHT_NAME),
+    (&s.sub_name, WEIGHT_SUB_NAME),
+    (description, WEIGHT_DESCRIPTION),
+  ]
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_normalize() {
+    assert_eq!(normalize("Virágföld"), "viragfold");
+    assert_eq!(normalize("ŐSZI lombseprű"), "oszi lombsepru");
+    assert_eq!(
+      normalize("Árvíztűrő tükörfúrógép"),
+      "arvizturo tukorfurogep"
+    );
+    assert_eq!(
+      tokenize("Kerti tömlő, 1/2\" (25m)"),
+      vec!["kerti", "tomlo", "1", "2", "25m"]
+    );
+  }
+
+  #[test]
+  fn test_rank() {
+    let docs = [
+      (1, "Kerti szerszám", "Tömlőhöz is"),
+      (2, "Kerti tömlő", ""),
+      (3, "Tömlőkocsi", ""),
+      (4, "Virágföld", ""),
+    ];
+    let hits = |query: &str| {
+      rank(
+        query,
+        docs.iter().map(|(id, name, description)| {
+          (
+            *id,
+            vec![(*name, WEIGHT_NAME), (*description, WEIGHT_DESCRIPTION)],
+          )
+        }),
+        0,
+      )
+      .into_iter()
+      .map(|h| h.id)
+      .collect::<Vec<u32>>()
+    };
+    // Exact name match first, then name prefix, then description prefix
+    assert_eq!(hits("tomlo"), vec![2, 3, 1]);
+    assert_eq!(hits("KERTI TÖMLŐ"), vec![2, 1]);
+    assert_eq!(hits("viragfold"), vec![4]);
+    assert_eq!(hits("ragf"), vec![4]);
+    assert_eq!(hits("locsoló"), Vec::<u32>::new());
+    assert_eq!(hits(""), vec![1, 2, 3, 4]);
+  }
+
+  #[test]
+  fn test_edit_distance() {
+    assert_eq!(edit_distance("tomlo", "tomlo"), 0);
+    assert_eq!(edit_distance("tomol", "tomlo"), 1);
+    assert_eq!(edit_distance("mutragya", "mutragyia"), 1);
+    assert_eq!(edit_distance("kerti", "kreti"), 1);
+    assert_eq!(edit_distance("fold", "fa"), 3);
+    assert_eq!(edit_distance("", "abc"), 3);
+  }
+
+  #[test]
+  fn test_fuzzy_rank() {
+    let docs = [
+      (1, "Műtrágya"),
+      (2, "Kerti tömlő"),
+      (3, "Tömlőkocsi"),
+      (4, "Virágföld"),
+    ];
+    let hits = |query: &str, max_typos: u32| {
+      rank(
+        query,
+        docs
+          .iter()
+          .map(|(id, name)| (*id, vec![(*name, WEIGHT_NAME)])),
+        max_typos,
+      )
+      .into_iter()
+      .map(|h| (h.id, h.score))
+      .collect::<Vec<(u32, u32)>>()
+    };
+    // Typos are not tolerated by default
+    assert_eq!(hits("mutragyia", 0), vec![]);
+    assert_eq!(hits("mutragyia", 1), vec![(1, 8)]);
+    // Swapped letters, partial word
+    assert_eq!(hits("kreti tomol", 1), vec![(2, 16)]);
+    assert_eq!(hits("tomlp", 1), vec![(2, 8), (3, 8)]);
+    // Two typos need a long token and a tolerance of 2
+    assert_eq!(hits("vragfodl", 1), vec![]);
+    assert_eq!(hits("vragfodl", 2), vec![(4, 4)]);
+    // Short tokens must match exactly
+    assert_eq!(hits("kre", 2), vec![]);
+    // Exact matches rank before fuzzy ones
+    assert_eq!(hits("tomlo", 1), vec![(2, 48), (3, 36)]);
+    assert!(check_typos(3).is_err());
+  }
+}
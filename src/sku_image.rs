@@ -0,0 +1,237 @@
+// Copyright (C) 2020 Peter Mezei
+//
+// This file is part of Gardenzilla.
+//
+// Gardenzilla is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// Gardenzilla is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Gardenzilla.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::prelude::*;
+use chrono::prelude::*;
+use image::{imageops::FilterType, DynamicImage, GenericImageView, ImageFormat, ImageOutputFormat};
+use std::path::PathBuf;
+
+/// Stored image variants
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageSize {
+  Thumbnail,
+  Medium,
+  Large,
+}
+
+impl ImageSize {
+  /// All the variants we store for every image
+  pub fn all() -> [ImageSize; 3] {
+    [ImageSize::Thumbnail, ImageSize::Medium, ImageSize::Large]
+  }
+  /// Side length in pixels
+  pub fn pixels(&self) -> u32 {
+    match self {
+      ImageSize::Thumbnail => 150,
+      ImageSize::Medium => 600,
+      ImageSize::Large => 1200,
+    }
+  }
+}
+
+impl std::fmt::Display for ImageSize {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ImageSize::Thumbnail => write!(f, "thumbnail"),
+      ImageSize::Medium => write!(f, "medium"),
+      ImageSize::Large => write!(f, "large"),
+    }
+  }
+}
+
+/// Generate new image ID based on the current time
+/// that is not in the taken image IDs
+pub fn new_image_id(taken: &[String]) -> String {
+  let mut base = Utc::now().timestamp_millis();
+  loop {
+    let image_id = format!("{:x}", base);
+    if !taken.contains(&image_id) {
+      return image_id;
+    }
+    base += 1;
+  }
+}
+
+/// Try to get image format from file extension
+pub fn format_from_extension(file_extension: &str) -> ServiceResult<ImageFormat> {
+  match file_extension
+    .trim()
+    .trim_start_matches('.')
+    .to_lowercase()
+    .as_str()
+  {
+    "jpg" | "jpeg" => Ok(ImageFormat::Jpeg),
+    "png" => Ok(ImageFormat::Png),
+    _ => Err(ServiceError::bad_request(&format!(
+      "Nem támogatott képformátum: {}. Csak jpg és png tölthető fel!",
+      file_extension
+    ))),
+  }
+}
+
+/// Process raw image bytes into normalized variants
+/// Image is cropped to a centered square, transparent
+/// parts are filled with white, then it is resized to
+/// every ImageSize and encoded as JPEG.
+/// Images are never upscaled.
+pub fn process(
+  image_bytes: &[u8],
+  format: ImageFormat,
+) -> ServiceResult<Vec<(ImageSize, Vec<u8>)>> {
+  let img = image::load_from_memory_with_format(image_bytes, format)
+    .map_err(|e| ServiceError::bad_request(&format!("A kép nem olvasható: {}", e)))?;
+  // Crop to centered square
+  let (width, height) = img.dimensions();
+  let side = width.min(height);
+  if side == 0 {
+    return Err(ServiceError::bad_request("A kép üres!"));
+  }
+  let cropped = img.crop_imm((width - side) / 2, (height - side) / 2, side, side);
+  // Fill transparent parts with white
+  let mut flattened = cropped.to_rgba8();
+  flattened.pixels_mut().for_each(|p| {
+    let alpha = p[3] as u32;
+    for c in 0..3 {
+      p[c] = ((p[c] as u32 * alpha + 255 * (255 - alpha)) / 255) as u8;
+    }
+    p[3] = 255;
+  });
+  let flattened = DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(flattened).to_rgb8());
+  // Resize and encode every variant
+  let mut res = Vec::new();
+  for size in ImageSize::all().iter() {
+    let pixels = size.pixels().min(side);
+    let resized = flattened.resize_exact(pixels, pixels, FilterType::Lanczos3);
+    let mut buffer = Vec::new();
+    resized
+      .write_to(&mut buffer, ImageOutputFormat::Jpeg(85))
+      .map_err(|e| ServiceError::internal_error(&format!("Image encoding error: {}", e)))?;
+    res.push((*size, buffer));
+  }
+  Ok(res)
+}
+
+/// Local disk storage of processed SKU images
+/// Images are stored as ROOT/SKU_ID/SIZE_IMAGE_ID.jpg
+#[derive(Clone)]
+pub struct ImageStore {
+  root: PathBuf,
+}
+
+impl ImageStore {
+  /// Create new image store at the given root path
+  pub fn new(root: PathBuf) -> Self {
+    Self { root }
+  }
+  /// Get the path of an image variant
+  pub fn image_path(&self, sku: u32, image_id: &str, size: ImageSize) -> PathBuf {
+    let mut path = self.root.clone();
+    path.push(sku.to_string());
+    path.push(format!("{}_{}.jpg", size, image_id));
+    path
+  }
+  /// Save processed image variants
+  pub fn save(
+    &self,
+    sku: u32,
+    image_id: &str,
+    variants: &[(ImageSize, Vec<u8>)],
+  ) -> ServiceResult<()> {
+    let mut dir = self.root.clone();
+    dir.push(sku.to_string());
+    std::fs::create_dir_all(&dir)
+      .map_err(|e| ServiceError::internal_error(&format!("Image store error: {}", e)))?;
+    for (size, bytes) in variants {
+      std::fs::write(self.image_path(sku, image_id, *size), bytes)
+        .map_err(|e| ServiceError::internal_error(&format!("Image store error: {}", e)))?;
+    }
+    Ok(())
+  }
+  /// Remove all the variants of an image
+  /// Missing files are ignored
+  pub fn remove(&self, sku: u32, image_id: &str) -> ServiceResult<()> {
+    for size in ImageSize::all().iter() {
+      match std::fs::remove_file(self.image_path(sku, image_id, *size)) {
+        Ok(_) => (),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+        Err(e) => {
+          return Err(ServiceError::internal_error(&format!(
+            "Image store error: {}",
+            e
+          )))
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use image::{Rgba, RgbaImage};
+
+  fn png_bytes(width: u32, height: u32) -> Vec<u8> {
+    let img = RgbaImage::from_fn(width, height, |x, _| match x % 2 {
+      0 => Rgba([255, 0, 0, 255]),
+      _ => Rgba([0, 0, 255, 0]),
+    });
+    let mut buffer = Vec::new();
+    DynamicImage::ImageRgba8(img)
+      .write_to(&mut buffer, ImageOutputFormat::Png)
+      .unwrap();
+    buffer
+  }
+
+  #[test]
+  fn test_new_image_id() {
+    let taken = vec![new_image_id(&[])];
+    assert_ne!(taken[0], new_image_id(&taken));
+  }
+
+  #[test]
+  fn test_format_from_extension() {
+    assert_eq!(format_from_extension("jpg").unwrap(), ImageFormat::Jpeg);
+    assert_eq!(format_from_extension(".JPEG").unwrap(), ImageFormat::Jpeg);
+    assert_eq!(format_from_extension("png").unwrap(), ImageFormat::Png);
+    assert!(format_from_extension("gif").is_err());
+  }
+
+  #[test]
+  fn test_process() {
+    // Large image is cropped to square and resized
+    let variants = process(&png_bytes(1600, 1300), ImageFormat::Png).unwrap();
+    assert_eq!(variants.len(), 3);
+    for (size, bytes) in variants {
+      let img = image::load_from_memory_with_format(&bytes, ImageFormat::Jpeg).unwrap();
+      assert_eq!(img.dimensions(), (size.pixels(), size.pixels()));
+    }
+    // Small image is never upscaled
+    let variants = process(&png_bytes(300, 200), ImageFormat::Png).unwrap();
+    let dimensions = variants
+      .iter()
+      .map(|(_, bytes)| {
+        image::load_from_memory_with_format(bytes, ImageFormat::Jpeg)
+          .unwrap()
+          .dimensions()
+      })
+      .collect::<Vec<(u32, u32)>>();
+    assert_eq!(dimensions, vec![(150, 150), (200, 200), (200, 200)]);
+    // Wrong bytes
+    assert!(process(&[1, 2, 3], ImageFormat::Png).is_err());
+  }
+}
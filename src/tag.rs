@@ -0,0 +1,112 @@
+// Copyright (C) 2020 Peter Mezei
+//
+// This file is part of Gardenzilla.
+//
+// Gardenzilla is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// Gardenzilla is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Gardenzilla.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::prelude::*;
+
+/// Max tag length in characters
+const TAG_MAX_LENGTH: usize = 32;
+
+/// Normalize tag
+/// Tags are lowercase, inner whitespaces are replaced
+/// with a single dash. Only letters, numbers, dash
+/// and underscore are allowed.
+pub fn normalize(tag: &str) -> ServiceResult<String> {
+  let res = tag
+    .split_whitespace()
+    .collect::<Vec<&str>>()
+    .join("-")
+    .to_lowercase();
+  if res.is_empty() || res.chars().count() > TAG_MAX_LENGTH {
+    return Err(ServiceError::bad_request(&format!(
+      "A címke 1-{} karakter hosszú lehet!",
+      TAG_MAX_LENGTH
+    )));
+  }
+  if !res
+    .chars()
+    .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+  {
+    return Err(ServiceError::bad_request(&format!(
+      "Hibás címke: {}. Csak betű, szám, kötőjel és alulvonás lehet benne!",
+      tag
+    )));
+  }
+  Ok(res)
+}
+
+/// Normalize tag list
+/// Result is sorted and contains no duplicates
+pub fn normalize_all(tags: &[String]) -> ServiceResult<Vec<String>> {
+  let mut res = tags
+    .iter()
+    .map(|t| normalize(t))
+    .collect::<ServiceResult<Vec<String>>>()?;
+  res.sort();
+  res.dedup();
+  Ok(res)
+}
+
+/// Add tags to a sorted tag list
+pub fn add(tags: &mut Vec<String>, new_tags: &[String]) {
+  tags.extend(new_tags.iter().cloned());
+  tags.sort();
+  tags.dedup();
+}
+
+/// Check if tags match the tag filter
+/// Empty filter matches everything.
+/// If any is true, one match is enough (OR),
+/// otherwise all the filter tags are required (AND).
+pub fn matches(tags: &[String], filter: &[String], any: bool) -> bool {
+  if filter.is_empty() {
+    return true;
+  }
+  match any {
+    true => filter.iter().any(|f| tags.contains(f)),
+    false => filter.iter().all(|f| tags.contains(f)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_normalize() {
+    assert_eq!(normalize(" Akció ").unwrap(), "akció");
+    assert_eq!(normalize("New  2026").unwrap(), "new-2026");
+    assert_eq!(normalize("in_door").unwrap(), "in_door");
+    assert!(normalize("").is_err());
+    assert!(normalize("   ").is_err());
+    assert!(normalize("50%").is_err());
+    assert!(normalize(&"a".repeat(33)).is_err());
+    assert_eq!(
+      normalize_all(&["Organic".into(), "akció".into(), "organic".into()]).unwrap(),
+      vec!["akció".to_string(), "organic".to_string()]
+    );
+  }
+
+  #[test]
+  fn test_matches() {
+    let tags = vec!["akció".to_string(), "organic".to_string()];
+    assert!(matches(&tags, &[], false));
+    assert!(matches(&tags, &["akció".into(), "organic".into()], false));
+    assert!(!matches(&tags, &["akció".into(), "indoor".into()], false));
+    assert!(matches(&tags, &["akció".into(), "indoor".into()], true));
+    assert!(!matches(&tags, &["indoor".into()], true));
+  }
+}
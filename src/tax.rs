@@ -0,0 +1,172 @@
+// Copyright (C) 2020 Peter Mezei
+//
+// This file is part of Gardenzilla.
+//
+// Gardenzilla is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// Gardenzilla is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Gardenzilla.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::prelude::*;
+use chrono::prelude::*;
+use packman::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum VatRate {
+  // VAT percentage, e.g. 27
+  Percentage(u32),
+  // Tax exempt (AAM)
+  Exempt,
+}
+
+impl std::fmt::Display for VatRate {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match &self {
+      VatRate::Percentage(p) => write!(f, "{}%", p),
+      VatRate::Exempt => write!(f, "AAM"),
+    }
+  }
+}
+
+impl VatRate {
+  pub fn try_from_str(from: &str) -> ServiceResult<VatRate> {
+    let from = from.trim();
+    match from.to_lowercase().as_str() {
+      "aam" | "exempt" => Ok(VatRate::Exempt),
+      rate => match rate.trim_end_matches('%').trim().parse::<u32>() {
+        Ok(p) if p <= 100 => Ok(VatRate::Percentage(p)),
+        _ => Err(ServiceError::bad_request(&format!(
+          "Hibás ÁFA kulcs: {}. Pl.: 27%, 5% vagy AAM",
+          from
+        ))),
+      },
+    }
+  }
+}
+
+/// VAT rate valid from the given time,
+/// until the next scheduled rate
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TaxRate {
+  pub rate: VatRate,
+  pub valid_from: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TaxClass {
+  /// Tax class ID
+  pub tax_class_id: u32,
+  /// Tax class name, e.g. Általános
+  pub name: String,
+  /// Rates ordered by valid_from
+  pub rates: Vec<TaxRate>,
+  /// Created by UID
+  pub created_by: u32,
+  /// Created at
+  pub created_at: DateTime<Utc>,
+}
+
+impl TaxClass {
+  /// Create new tax class with a rate valid from now
+  pub fn new(tax_class_id: u32, name: String, rate: VatRate, created_by: u32) -> Self {
+    let created_at = Utc::now();
+    Self {
+      tax_class_id,
+      name,
+      rates: vec![TaxRate {
+        rate,
+        valid_from: created_at,
+      }],
+      created_by,
+      created_at,
+    }
+  }
+  /// Schedule new rate
+  /// Rate valid from the very same time is replaced
+  pub fn schedule_rate(&mut self, rate: VatRate, valid_from: DateTime<Utc>) -> &Self {
+    self.rates.retain(|r| r.valid_from != valid_from);
+    self.rates.push(TaxRate { rate, valid_from });
+    self.rates.sort_by_key(|r| r.valid_from);
+    self
+  }
+  /// Get rate valid at the given time
+  pub fn rate_at(&self, at: DateTime<Utc>) -> Option<VatRate> {
+    self
+      .rates
+      .iter()
+      .rev()
+      .find(|r| r.valid_from <= at)
+      .map(|r| r.rate)
+  }
+}
+
+impl Default for TaxClass {
+  fn default() -> Self {
+    Self {
+      tax_class_id: 0,
+      name: String::default(),
+      rates: Vec::new(),
+      created_by: 0,
+      created_at: Utc::now(),
+    }
+  }
+}
+
+impl TryFrom for TaxClass {
+  type TryFrom = TaxClass;
+}
+
+impl VecPackMember for TaxClass {
+  type Out = u32;
+  fn get_id(&self) -> &Self::Out {
+    &self.tax_class_id
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_vat_rate_try_from_str() {
+    assert_eq!(
+      VatRate::try_from_str("27%").unwrap(),
+      VatRate::Percentage(27)
+    );
+    assert_eq!(
+      VatRate::try_from_str(" 5 ").unwrap(),
+      VatRate::Percentage(5)
+    );
+    assert_eq!(VatRate::try_from_str("aam").unwrap(), VatRate::Exempt);
+    assert!(VatRate::try_from_str("101%").is_err());
+    assert!(VatRate::try_from_str("sok").is_err());
+    assert_eq!(VatRate::Percentage(18).to_string(), "18%");
+  }
+
+  #[test]
+  fn test_rate_at() {
+    let mut tax_class = TaxClass::new(1, "Általános".into(), VatRate::Percentage(27), 1);
+    let now = Utc::now();
+    let next_year = now + chrono::Duration::days(365);
+    tax_class.schedule_rate(VatRate::Percentage(25), next_year);
+    assert_eq!(tax_class.rate_at(now), Some(VatRate::Percentage(27)));
+    assert_eq!(
+      tax_class.rate_at(next_year + chrono::Duration::days(1)),
+      Some(VatRate::Percentage(25))
+    );
+    assert_eq!(tax_class.rate_at(now - chrono::Duration::days(1)), None);
+    // Same time is replaced
+    tax_class.schedule_rate(VatRate::Percentage(26), next_year);
+    assert_eq!(tax_class.rates.len(), 2);
+    assert_eq!(tax_class.rate_at(next_year), Some(VatRate::Percentage(26)));
+  }
+}
@@ -0,0 +1,205 @@
+// Copyright (C) 2020 Peter Mezei
+//
+// This file is part of Gardenzilla.
+//
+// Gardenzilla is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 2 of the License, or
+// (at your option) any later version.
+//
+// Gardenzilla is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Gardenzilla.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::prelude::*;
+use crate::product::{Product, Sku};
+use crate::quantity::{Decimal, Quantity, Unit};
+
+/// Violated invariant of a Product or Sku
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+  /// Related field name, e.g. quantity
+  pub field: String,
+  /// Hungarian error message
+  pub message: String,
+}
+
+impl Violation {
+  fn new(field: &str, message: &str) -> Self {
+    Self {
+      field: field.to_string(),
+      message: message.to_string(),
+    }
+  }
+}
+
+impl std::fmt::Display for Violation {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}: {}", self.field, self.message)
+  }
+}
+
+/// Collect every violated Product invariant
+pub fn product(p: &Product) -> Vec<Violation> {
+  let mut res = Vec::new();
+  if p.name.trim().is_empty() {
+    res.push(Violation::new("name", "A termék neve nem lehet üres!"));
+  }
+  res
+}
+
+/// Collect every violated Sku invariant
+/// Parent related rules are checked only if parent is given
+pub fn sku(s: &Sku, parent: Option<&Product>) -> Vec<Violation> {
+  let mut res = Vec::new();
+  // Quantity rules
+  if s.quantity.total() == Decimal::default() {
+    res.push(Violation::new("quantity", "A mennyiség nem lehet 0!"));
+  }
+  match s.quantity {
+    Quantity::Float(_) => res.push(Violation::new(
+      "quantity",
+      "Pontatlan (lebegőpontos) mennyiség nem adható meg!",
+    )),
+    Quantity::Decimal(_) if matches!(s.unit, Unit::Piece | Unit::Pair | Unit::Set) => {
+      res.push(Violation::new(
+        "quantity",
+        &format!(
+          "A(z) {} mértékegységnél csak egész mennyiség adható meg!",
+          s.unit
+        ),
+      ))
+    }
+    _ => (),
+  }
+  // Divide rules
+  if s.can_divide {
+    if !matches!(s.quantity, Quantity::Simple(_)) {
+      res.push(Violation::new(
+        "can_divide",
+        "Csak egyszerű mennyiség lehet osztható!",
+      ));
+    }
+    if s.is_bundle() {
+      res.push(Violation::new("can_divide", "Csomag nem lehet osztható!"));
+    }
+    if let Err(e) = s.divide_rule.validate(&s.quantity) {
+      res.push(Violation::new("divide_rule", &e));
+    }
+  }
+  // Packaging rules
+  // A changed quantity can make the packaging too large
+  if !s.packaging.is_empty() {
+    if let Err(e) = s.packaging_total(s.packaging.len() - 1) {
+      res.push(Violation::new("packaging", &e));
+    }
+  }
+  // Parent rules
+  if let Some(parent) = parent {
+    if s.product_id != parent.product_id {
+      res.push(Violation::new(
+        "product_id",
+        "A SKU másik termékhez tartozik!",
+      ));
+    }
+    if s.unit != parent.unit {
+      res.push(Violation::new(
+        "unit",
+        &format!(
+          "A SKU mértékegysége ({}) eltér a termékétől ({})!",
+          s.unit, parent.unit
+        ),
+      ));
+    }
+    if parent.discontinued && !s.discontinued {
+      res.push(Violation::new(
+        "discontinued",
+        "A termék kifutó termék. Nem lehet a SKU kivétel",
+      ));
+    }
+  }
+  res
+}
+
+/// Turn violations into a single error containing all of them
+pub fn check(violations: Vec<Violation>) -> ServiceResult<()> {
+  match violations.is_empty() {
+    true => Ok(()),
+    false => Err(ServiceError::Invalid(violations)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::packaging::PackagingLevel;
+
+  fn fields(violations: Vec<Violation>) -> Vec<String> {
+    violations.into_iter().map(|v| v.field).collect()
+  }
+
+  #[test]
+  fn test_sku() {
+    let mut parent = Product::new(1, "Virágföld".into(), "".into(), Unit::Gram, 1);
+    let mut s = Sku::new(1, 1, &parent, "".into(), Quantity::Simple(500), 1);
+    assert_eq!(fields(sku(&s, Some(&parent))), Vec::<String>::new());
+    // Divisible SKU switched to complex quantity
+    s.can_divide = true;
+    s.quantity = Quantity::Complex(3, 500);
+    assert_eq!(fields(sku(&s, Some(&parent))), vec!["can_divide"]);
+    // Zero quantity reported together with the divide rule
+    s.quantity = Quantity::Simple(0);
+    assert_eq!(
+      fields(sku(&s, Some(&parent))),
+      vec!["quantity", "divide_rule"]
+    );
+    // Discontinued parent
+    s.can_divide = false;
+    s.quantity = Quantity::Simple(500);
+    parent.discontinued = true;
+    assert_eq!(fields(sku(&s, Some(&parent))), vec!["discontinued"]);
+    assert_eq!(fields(sku(&s, None)), Vec::<String>::new());
+  }
+
+  #[test]
+  fn test_sku_count_unit() {
+    let parent = Product::new(1, "Kesztyű".into(), "".into(), Unit::Pair, 1);
+    let mut s = Sku::new(1, 1, &parent, "".into(), Quantity::Simple(2), 1);
+    assert!(sku(&s, Some(&parent)).is_empty());
+    s.quantity = Quantity::Decimal(Decimal::try_from_str("1.5").unwrap());
+    assert_eq!(fields(sku(&s, Some(&parent))), vec!["quantity"]);
+    s.quantity = Quantity::Float(1.5);
+    assert_eq!(fields(sku(&s, Some(&parent))), vec!["quantity"]);
+  }
+
+  #[test]
+  fn test_sku_packaging() {
+    let parent = Product::new(1, "Virágföld".into(), "".into(), Unit::Gram, 1);
+    let mut s = Sku::new(1, 1, &parent, "".into(), Quantity::Simple(500), 1);
+    let level = |count: u32| PackagingLevel {
+      name: "karton".into(),
+      count,
+      barcode: None,
+    };
+    assert!(s
+      .set_packaging(vec![level(u32::MAX), level(u32::MAX)])
+      .is_err());
+    s.set_packaging(vec![level(u32::MAX)]).unwrap();
+    assert!(sku(&s, Some(&parent)).is_empty());
+    // Quantity grows too large for the packaging
+    s.quantity = Quantity::Complex(u32::MAX, u32::MAX);
+    assert_eq!(fields(sku(&s, Some(&parent))), vec!["packaging"]);
+  }
+
+  #[test]
+  fn test_product() {
+    let mut p = Product::new(1, "Virágföld".into(), "".into(), Unit::Gram, 1);
+    assert!(product(&p).is_empty());
+    p.name = " ".into();
+    assert_eq!(fields(product(&p)), vec!["name"]);
+  }
+}